@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
 use count_min_sketch::CountMinSketch64;
 use criterion::{Criterion, criterion_group, criterion_main};
+use rust_frequency_counter::sketch::concurrent_frequency_sketch::ConcurrentFrequencySketch;
 use rust_frequency_counter::sketch::frequency_count_sketch::FrequencyCountSketch;
+use rust_frequency_counter::sketch::workload;
 
 fn test_count_min_sketch(max: usize) {
     let mut cms = CountMinSketch64::<u64>::new(max , 0.99, 2.0).unwrap();
@@ -16,6 +21,20 @@ fn test_frequency_count_sketch(max: usize) {
     }
 }
 
+fn test_frequency_count_sketch_unchecked(max: usize) {
+    let mut sketch = FrequencyCountSketch::new(max);
+    for i in 0..max {
+        sketch.increment_unchecked(i)
+    }
+}
+
+fn test_frequency_count_sketch_u64(max: usize) {
+    let mut sketch = FrequencyCountSketch::new(max);
+    for i in 0..max as u64 {
+        sketch.increment_u64(i.wrapping_mul(0x9e3779b97f4a7c15))
+    }
+}
+
 fn sketch_1_benchmark(c: &mut Criterion) {
     c.bench_function("sketch1", |b| b.iter(|| test_count_min_sketch(100000)));
 }
@@ -24,5 +43,76 @@ fn sketch_2_benchmark(c: &mut Criterion) {
     c.bench_function("sketch2", |b| b.iter(|| test_frequency_count_sketch(100000)));
 }
 
-criterion_group!(benches, sketch_1_benchmark, sketch_2_benchmark);
+fn sketch_unchecked_benchmark(c: &mut Criterion) {
+    c.bench_function("sketch_unchecked", |b| b.iter(|| test_frequency_count_sketch_unchecked(100000)));
+}
+
+// Compare with sketch2 to measure the cost of the default hasher
+fn sketch_u64_benchmark(c: &mut Criterion) {
+    c.bench_function("sketch_u64", |b| b.iter(|| test_frequency_count_sketch_u64(100000)));
+}
+
+// Compare runs with and without `--features prefetch` to measure the effect of prefetching
+fn sketch_frequency_benchmark(c: &mut Criterion) {
+    let sketch = workload::load_zipfian(100000, 100000, 1.0);
+    c.bench_function("sketch_frequency", |b| {
+        b.iter(|| (0..100000u64).map(|i| sketch.frequency(i) as u64).sum::<u64>())
+    });
+}
+
+fn sketch_increment_benchmark(c: &mut Criterion) {
+    let mut sketch = FrequencyCountSketch::new(100000);
+    c.bench_function("sketch_increment", |b| {
+        b.iter(|| (0..100000u64).for_each(|i| sketch.increment(i)))
+    });
+}
+
+// Four threads incrementing the same keys contend on the blocks of the hottest keys
+fn sketch_concurrent_benchmark(c: &mut Criterion) {
+    let sketch = ConcurrentFrequencySketch::new(100000);
+    c.bench_function("sketch_concurrent", |b| {
+        b.iter(|| {
+            std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| workload::zipfian_stream(100000, 25000, 1.0).for_each(|i| sketch.increment(i)));
+                }
+            })
+        })
+    });
+}
+
+fn load_zipfian_hash_map(key_space: usize, n: usize, skew: f64) -> HashMap<u64, u32> {
+    let mut counts = HashMap::new();
+    for key in workload::zipfian_stream(key_space, n, skew) {
+        *counts.entry(key).or_insert(0) += 1;
+    }
+    counts
+}
+
+// Exact counting baseline for sketch_zipfian; the memory of both is printed once
+fn hash_map_zipfian_benchmark(c: &mut Criterion) {
+    let counts = load_zipfian_hash_map(100000, 100000, 1.0);
+    // Each bucket holds the entry plus one control byte
+    let map_bytes = counts.capacity() * (size_of::<(u64, u32)>() + 1);
+    let sketch_bytes = workload::load_zipfian(100000, 100000, 1.0).memory_usage_bytes();
+    println!("memory: hash map {} bytes for {} keys, sketch {} bytes", map_bytes, counts.len(), sketch_bytes);
+    c.bench_function("hash_map_zipfian", |b| b.iter(|| load_zipfian_hash_map(100000, 100000, 1.0)));
+}
+
+fn sketch_zipfian_benchmark(c: &mut Criterion) {
+    c.bench_function("sketch_zipfian", |b| b.iter(|| workload::load_zipfian(100000, 100000, 1.0)));
+}
+
+criterion_group!(
+    benches,
+    sketch_1_benchmark,
+    sketch_2_benchmark,
+    sketch_unchecked_benchmark,
+    sketch_u64_benchmark,
+    sketch_frequency_benchmark,
+    sketch_increment_benchmark,
+    sketch_concurrent_benchmark,
+    sketch_zipfian_benchmark,
+    hash_map_zipfian_benchmark
+);
 criterion_main!(benches);
\ No newline at end of file
@@ -0,0 +1,123 @@
+use std::cmp::min;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::sketch::frequency_count_sketch::{
+    default_hash_code, derive_positions, dimensions_for, halved_size, spread, CounterWord,
+};
+
+/// A variant of <tt>FrequencyCountSketch</tt> that can be shared between threads. The table has the
+/// default layout, where all four counters of an element live in one 64-byte block, and every block
+/// is guarded by its own lock. Increments of elements in different blocks therefore proceed in
+/// parallel, and each block is aligned to its own cache lines so that neighbouring blocks do not
+/// falsely share them. The sample accounting is atomic, and the thread whose increment reaches
+/// the sample size ages the table one block at a time while other threads keep counting, so
+/// increments racing with a reset may be aged or not.
+#[derive(Debug)]
+pub struct ConcurrentFrequencySketch {
+    // Frequency reduction threshold
+    sample_size: usize,
+    block_mask: usize,
+    // Access frequency container, one locked block of 8 words per entry
+    blocks: Vec<Block>,
+    size: AtomicUsize,
+    max_size: usize,
+    // Whether a thread is currently aging the table
+    resetting: AtomicBool,
+}
+
+/// A block of the table with its lock, padded to whole cache lines.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Block(Mutex<[u64; 8]>);
+
+impl ConcurrentFrequencySketch {
+    /// Initializes a sketch that can estimate the popularity of elements given the maximum size of
+    /// the cache, with the same table as <tt>FrequencyCountSketch::new</tt>.
+    pub fn new(maximum_size: usize) -> Self {
+        let (table_len, maximum, sample_size) = dimensions_for(maximum_size);
+        Self {
+            sample_size,
+            block_mask: (table_len >> 3) - 1,
+            blocks: (0..table_len >> 3).map(|_| Block::default()).collect(),
+            size: AtomicUsize::new(0),
+            max_size: maximum,
+            resetting: AtomicBool::new(false),
+        }
+    }
+
+    /// Return max size of this sketch
+    pub fn get_max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return table len of this sketch
+    pub fn get_table_len(&self) -> usize {
+        self.blocks.len() << 3
+    }
+
+    /// Return the number of increments that raised at least one counter since the last reset.
+    pub fn estimated_cardinality(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Return the estimated number of occurrences of an element, up to the maximum (15).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        let (block, words, nibbles) = self.positions(default_hash_code(e));
+        let table = self.lock(block);
+        (0..4).map(|i| ((table[words[i]] >> (nibbles[i] << 2)) & 0xf) as u8).fold(15, min)
+    }
+
+    /// Increments the popularity of the element if it does not exceed the maximum (15), locking only
+    /// the element's block. The popularity of all elements will be periodically down sampled when
+    /// the observed events exceed a threshold.
+    pub fn increment<E: Hash>(&self, e: E) {
+        let (block, words, nibbles) = self.positions(default_hash_code(e));
+        let mut added = false;
+        {
+            let mut table = self.lock(block);
+            for i in 0..4 {
+                added |= table[words[i]].increment_nibble(nibbles[i]);
+            }
+        }
+
+        if added && self.size.fetch_add(1, Ordering::Relaxed) + 1 >= self.sample_size {
+            self.try_reset();
+        }
+    }
+
+    /// Reduces every counter by half of its original value, locking one block at a time.
+    pub fn reset(&self) {
+        let mut odd = 0usize;
+        for block in 0..self.blocks.len() {
+            odd += self.lock(block).iter_mut().map(|word| word.halve_nibbles()).sum::<usize>();
+        }
+        let _ = self.size.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |size| {
+            Some(halved_size(size, odd))
+        });
+    }
+
+    /// Resets the sketch unless another thread is already doing so.
+    fn try_reset(&self) {
+        if self.resetting.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            if self.size.load(Ordering::Relaxed) >= self.sample_size {
+                self.reset();
+            }
+            self.resetting.store(false, Ordering::Release);
+        }
+    }
+
+    /// Return the index of the element's block, and the word within the block and the nibble
+    /// within the word of each of its four counters.
+    fn positions(&self, hash_code: u64) -> (usize, [usize; 4], [usize; 4]) {
+        let (words, nibbles) = derive_positions(self.block_mask, 3, spread(hash_code));
+        (words[0] >> 3, words.map(|word| word & 7), nibbles)
+    }
+
+    /// Locks the block, recovering it if a thread panicked while holding the lock since the counters
+    /// are always left consistent.
+    fn lock(&self, block: usize) -> MutexGuard<'_, [u64; 8]> {
+        self.blocks[block].0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,140 @@
+use std::cmp::min;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use crate::sketch::frequency_count_sketch::{
+    count_mean_min, counted_size, default_hash_code, dimensions_for, halve_table, nibble_sum_word, rehash, spread,
+    CounterWord,
+};
+
+/// A variant of <tt>FrequencyCountSketch</tt> whose depth, the number of counters read and
+/// min-reduced per element, is chosen at compile time. The depth fixes the confidence of the
+/// estimates at <tt>1 - 2^-D</tt>: depth 3 saves hashing work at 87.5% confidence, while depth 8
+/// reaches 99.6% at the cost of more collisions per counter. The counters still live in one 64-byte
+/// block per element, split into <tt>D</tt> segments of whole words, so <tt>D</tt> must be between 1
+/// and 8; other depths fail to compile. The default depth of 4 uses exactly the layout of
+/// <tt>FrequencyCountSketch</tt>.
+#[derive(Debug, Clone)]
+pub struct DepthFrequencySketch<const D: usize = 4> {
+    // Frequency reduction threshold
+    sample_size: usize,
+    block_mask: usize,
+    // Access frequency container
+    table: Vec<u64>,
+    table_len: usize,
+    size: usize,
+    max_size: usize,
+}
+
+impl<const D: usize> DepthFrequencySketch<D> {
+    // Evaluated when the sketch is instantiated, rejecting unsupported depths at compile time
+    const VALID_DEPTH: () = assert!(D >= 1 && D <= 8, "depth must be between 1 and 8");
+
+    /// Initializes a sketch that can estimate the popularity of elements given the maximum size of
+    /// the cache, with the same table as <tt>FrequencyCountSketch::new</tt>.
+    pub fn new(maximum_size: usize) -> Self {
+        let () = Self::VALID_DEPTH;
+        let (table_len, maximum, sample_size) = dimensions_for(maximum_size);
+        Self {
+            sample_size,
+            block_mask: (table_len >> 3) - 1,
+            table: vec![0; table_len],
+            table_len,
+            size: 0,
+            max_size: maximum,
+        }
+    }
+
+    /// Return the number of counters per element.
+    pub fn depth(&self) -> usize {
+        D
+    }
+
+    /// Return max size of this sketch
+    pub fn get_max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return table len of this sketch
+    pub fn get_table_len(&self) -> usize {
+        self.table_len
+    }
+
+    /// Return the number of bytes used by the counter table.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.table_len * size_of::<u64>()
+    }
+
+    /// Return the estimated number of occurrences of an element, up to the maximum (15).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        let positions = self.positions(default_hash_code(e));
+        positions
+            .iter()
+            .map(|&(word, nibble)| ((self.table[word] >> (nibble << 2)) & 0xf) as u8)
+            .fold(15, min)
+    }
+
+    /// Return an estimate of the number of occurrences of an element corrected for collision noise,
+    /// following the count-mean-min estimator of <tt>FrequencyCountSketch::frequency_cmm</tt>, with
+    /// one row per depth. With many colliding keys this is closer to the true count than the
+    /// minimum, at the cost of sometimes underestimating, and more rows make the median more robust.
+    /// The default depth of 4 returns exactly the estimate of <tt>frequency_cmm</tt>. This scans the
+    /// whole table.
+    pub fn frequency_corrected<E: Hash>(&self, e: E) -> f64 {
+        let mut row_mass = [0u64; D];
+        let mut row_width = [0usize; D];
+        for (i, &word) in self.table.iter().enumerate() {
+            // The row owning this word's segment, the last one starting at or before it
+            let row = (((i & 7) + 1) * D - 1) >> 3;
+            row_mass[row] += nibble_sum_word(word) as u64;
+            row_width[row] += 16;
+        }
+        let count = self
+            .positions(default_hash_code(e))
+            .map(|(word, nibble)| ((self.table[word] >> (nibble << 2)) & 0xf) as u8);
+        count_mean_min(count, row_mass, row_width)
+    }
+
+    /// Increments the popularity of the element if it does not exceed the maximum (15). The popularity
+    /// of all elements will be periodically down sampled when the observed events exceed a threshold.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        let positions = self.positions(default_hash_code(e));
+        let mut added = false;
+        for (word, nibble) in positions {
+            added |= self.increment_at(word, nibble);
+        }
+
+        if added {
+            self.size = counted_size(self.size, self.sample_size);
+            if self.size >= self.sample_size {
+                self.reset();
+            }
+        }
+    }
+
+    /// Reduces every counter by half of its original value.
+    pub fn reset(&mut self) {
+        self.size = halve_table(&mut self.table, self.size);
+    }
+
+    /// Return the table word and nibble index of each of the element's counters. Row <tt>i</tt> owns
+    /// the words <tt>8i/D..8(i+1)/D</tt> of the block and selects a counter among them with one byte
+    /// of the counter hash, rehashed again for the rows beyond the fourth.
+    fn positions(&self, hash_code: u64) -> [(usize, usize); D] {
+        let block_hash = spread(hash_code);
+        let counter_hash = rehash(block_hash);
+        let extra_hash = rehash(counter_hash);
+        let block = (block_hash & self.block_mask) << 3;
+        std::array::from_fn(|i| {
+            let h = if i < 4 { counter_hash >> (i << 3) } else { extra_hash >> ((i - 4) << 3) } & 0xff;
+            let start = (i << 3) / D;
+            let len = ((i + 1) << 3) / D - start;
+            (block + start + h % len, (h / len) & 15)
+        })
+    }
+
+    /// Increments the specified counter by 1 if it is not already at the maximum value (15).
+    fn increment_at(&mut self, i: usize, j: usize) -> bool {
+        self.table[i].increment_nibble(j)
+    }
+}
@@ -0,0 +1,42 @@
+/// The number of hash bits selecting a register.
+const PRECISION: u32 = 8;
+/// The number of registers, each holding the longest run of leading zeros observed for it.
+const REGISTERS: usize = 1 << PRECISION;
+
+/// A HyperLogLog [1] estimator of the number of distinct hash codes observed. With 256 one-byte
+/// registers the standard error of the estimate is about 6.5%.
+///
+/// [1] HyperLogLog: the analysis of a near-optimal cardinality estimation algorithm
+/// http://algo.inria.fr/flajolet/Publications/FlFuGaMe07.pdf
+#[derive(Debug, Clone)]
+pub(crate) struct DistinctCounter {
+    registers: [u8; REGISTERS],
+}
+
+impl DistinctCounter {
+    pub(crate) fn new() -> Self {
+        Self { registers: [0; REGISTERS] }
+    }
+
+    /// Records an observation of the given hash code. The top bits select the register and the rank
+    /// is the position of the first set bit among the remaining ones.
+    pub(crate) fn observe(&mut self, hash_code: u64) {
+        let index = (hash_code >> (64 - PRECISION)) as usize;
+        let rank = ((hash_code << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Return the estimated number of distinct hash codes observed, falling back to linear counting
+    /// of the empty registers for small cardinalities.
+    pub(crate) fn estimate(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| (-(r as i32) as f64).exp2()).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        let estimate = if raw <= 2.5 * m && zeros > 0 { m * (m / zeros as f64).ln() } else { raw };
+        estimate.round() as u64
+    }
+}
@@ -0,0 +1,79 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::sketch::frequency_count_sketch::{MAX_BLOCK_WORDS, MAX_TABLE_LEN};
+
+/// Errors returned when a <tt>FrequencyCountSketch</tt> cannot be built or combined with the
+/// requested parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SketchError {
+    /// The table length is not a power of two within the supported range.
+    InvalidTableLength(usize),
+    /// The number of decoded counters does not match the table derived from the maximum size.
+    InvalidCounterCount { expected: usize, actual: usize },
+    /// A decoded counter exceeds the maximum value (15).
+    CounterOutOfRange { index: usize, value: u8 },
+    /// The number of bytes does not match the table derived from the maximum size.
+    InvalidByteLength { expected: usize, actual: usize },
+    /// The decay factor does not lie strictly between 0 and 1.
+    InvalidDecay(f32),
+    /// A table word index lies outside the table.
+    WordIndexOutOfRange(usize),
+    /// The block length is not a power of two within the supported range.
+    InvalidBlockWords(usize),
+    /// Two sketches that must be combined have different table lengths.
+    TableLengthMismatch { expected: usize, actual: usize },
+    /// Two sketches that must be combined place the counters of an element differently.
+    LayoutMismatch,
+    /// An internal invariant of the sketch does not hold.
+    CorruptState(String),
+    /// A sliding window sketch needs at least one sub-window.
+    InvalidWindowCount(usize),
+    /// No supported table keeps the expected collision rate within the target.
+    UnreachableCollisionRate(f64),
+}
+
+impl fmt::Display for SketchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SketchError::InvalidTableLength(len) => write!(
+                f,
+                "invalid table length {}: must be a power of two between 8 and {}",
+                len, MAX_TABLE_LEN
+            ),
+            SketchError::InvalidCounterCount { expected, actual } => {
+                write!(f, "expected {} counters, found {}", expected, actual)
+            }
+            SketchError::CounterOutOfRange { index, value } => {
+                write!(f, "counter {} has value {}, above the maximum of 15", index, value)
+            }
+            SketchError::InvalidByteLength { expected, actual } => {
+                write!(f, "expected {} bytes, found {}", expected, actual)
+            }
+            SketchError::InvalidDecay(decay) => {
+                write!(f, "invalid decay {}: must lie strictly between 0 and 1", decay)
+            }
+            SketchError::WordIndexOutOfRange(index) => write!(f, "word index {} is outside the table", index),
+            SketchError::InvalidBlockWords(words) => write!(
+                f,
+                "invalid block length {}: must be a power of two between 8 and {} words",
+                words, MAX_BLOCK_WORDS
+            ),
+            SketchError::TableLengthMismatch { expected, actual } => {
+                write!(f, "expected a table of {} words, found {}", expected, actual)
+            }
+            SketchError::LayoutMismatch => write!(f, "the sketches use different block layouts"),
+            SketchError::CorruptState(reason) => write!(f, "corrupt sketch state: {}", reason),
+            SketchError::InvalidWindowCount(windows) => {
+                write!(f, "invalid window count {}: must be at least 1", windows)
+            }
+            SketchError::UnreachableCollisionRate(rate) => write!(
+                f,
+                "collision rate {} cannot be reached with a table of at most {} words",
+                rate, MAX_TABLE_LEN
+            ),
+        }
+    }
+}
+
+impl Error for SketchError {}
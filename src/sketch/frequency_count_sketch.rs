@@ -1,45 +1,675 @@
-use std::cmp::{max, min};
+use std::cmp::{max, min, Ordering, Reverse};
 use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::mem::{self, size_of};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "std")]
+use std::io::BufRead;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cms-adapter")]
+use count_min_sketch::CountMinSketch64;
+
+#[cfg(feature = "distinct-count")]
+use crate::sketch::distinct_counter::DistinctCounter;
+use crate::sketch::error::SketchError;
+use siphasher::sip::SipHasher13;
+
+/// The smallest supported table length. An element's four counters are spread over the four
+/// 16-byte segments of a 64-byte block, so the table must hold at least one block of 8 words.
+pub const MIN_TABLE_LEN: usize = 8;
+/// The largest supported table length, matching the clamp applied by <tt>new</tt>.
+pub const MAX_TABLE_LEN: usize = 1 << 30;
+
+/// The number of table words in the block holding an element's counters by default, the 64 bytes of
+/// a typical L1 cache line.
+pub const DEFAULT_BLOCK_WORDS: usize = 8;
+/// The largest supported number of table words in a block.
+pub const MAX_BLOCK_WORDS: usize = 32;
+/// The largest value a counter can hold, and so the largest estimated frequency.
+pub const MAX_FREQUENCY: u8 = 15;
+/// The number of counters of an element, one in each row of the sketch, whose minimum is its
+/// estimated frequency. Each row owns a quarter of every block.
+pub const DEPTH: usize = 4;
+
+/// The number of table words an incremental reset ages per increment.
+const RESET_CHUNK_WORDS: usize = 8;
+/// The number of elements <tt>increment_batch_with_aging</tt> hashes ahead of counting them.
+const BATCH_LEN: usize = 16;
+/// The number of consecutive increments of the same hash code after which
+/// <tt>increment_checked_key</tt> reports the hash as suspiciously constant.
+pub const SUSPICIOUS_HASH_RUN: usize = 256;
 
 /// This struct maintains a 4-bit CountMinSketch [1] with periodic aging to provide the popularity
 /// history for the TinyLfu admission policy [2]. The time and space efficiency of the sketch
 /// allows it to cheaply estimate the frequency of an entry in a stream of cache access events.
-#[derive(Debug)]
+/// Workloads that only need to tell apart a handful of occurrences can use the 2-bit
+/// <tt>FrequencyCountSketch2</tt> instead.
+#[derive(Debug, Clone)]
 pub struct FrequencyCountSketch {
     // Frequency reduction threshold
     sample_size: usize,
+    // Binary logarithm of the block length in words, and the mask selecting a block
+    block_shift: u32,
     block_mask: usize,
     // Access frequency container
-    table: Vec<u64>,
+    table: Table,
     table_len: usize,
     size: usize,
     max_size: usize,
+    // Whether reaching the sample size resets the sketch, and whether reset penalizes saturated
+    // counters beyond halving
+    aging_enabled: bool,
+    aggressive_aging: bool,
+    // Callback invoked after every reset
+    reset_hook: Option<ResetHook>,
+    // Bounds of the self-tuned sample size, when adaptive
+    adaptive_bounds: Option<(usize, usize)>,
+    // Whether aging is spread across increments, and the progress of the current sweep
+    incremental_reset: bool,
+    reset_sweep: Option<ResetSweep>,
+    // Whether each depth selects its block with its own hash instead of sharing one block
+    independent_rows: bool,
+    // Fixed-point reset multiplier in 256ths, when not halving
+    decay_factor: Option<u64>,
+    // Key of the SipHash hasher placing the elements, when seeded
+    hash_seed: Option<u64>,
+    // Number of resets since the sketch was created
+    reset_count: u64,
+    // Latest hash code passed to increment_checked_key and how many times in a row it was seen
+    hash_run: (u64, usize),
+    // Number of increments counted towards the sample since creation, and how many make it warm
+    events: u64,
+    warmup: u64,
+    // Time accumulated towards the next wall-clock driven reset
+    #[cfg(feature = "std")]
+    elapsed: Duration,
+    // Period of the resets driven by the instants passed to increment_at_time, and the latest one
+    #[cfg(feature = "std")]
+    reset_period: Option<Duration>,
+    #[cfg(feature = "std")]
+    last_instant: Option<Instant>,
+    // Estimator of the distinct keys observed, unaffected by aging
+    #[cfg(feature = "distinct-count")]
+    distinct: DistinctCounter,
+}
+
+/// The progress of a reset that ages the table in chunks.
+#[derive(Debug, Clone, Default)]
+struct ResetSweep {
+    // Next table word to age
+    cursor: usize,
+    // Odd and non-zero counters found so far
+    odd: usize,
+    nonzero: usize,
+}
+
+/// One 64-byte block of table words, aligned so that a block never straddles two cache lines.
+#[repr(C, align(64))]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CacheLine([u64; 8]);
+
+/// The counter table, allocated as whole cache lines and dereferencing to its words.
+#[derive(Clone, PartialEq)]
+struct Table(Vec<CacheLine>);
+
+impl Table {
+    /// Allocates a zeroed table of the given number of words, which must be a multiple of 8.
+    fn zeroed(len: usize) -> Self {
+        Self(vec![CacheLine::default(); len >> 3])
+    }
+
+    /// Copies the words, whose number must be a multiple of 8, into an aligned table.
+    fn from_words(words: &[u64]) -> Self {
+        let mut table = Self::zeroed(words.len());
+        table.copy_from_slice(words);
+        table
+    }
+}
+
+impl Deref for Table {
+    type Target = [u64];
+
+    fn deref(&self) -> &[u64] {
+        // SAFETY: CacheLine is a repr(C) array of 8 words whose size equals its alignment, so the
+        // lines are laid out as one contiguous run of initialized words
+        unsafe { std::slice::from_raw_parts(self.0.as_ptr() as *const u64, self.0.len() << 3) }
+    }
+}
+
+impl DerefMut for Table {
+    fn deref_mut(&mut self) -> &mut [u64] {
+        // SAFETY: as for deref, and the words are borrowed mutably for as long as the table is
+        unsafe { std::slice::from_raw_parts_mut(self.0.as_mut_ptr() as *mut u64, self.0.len() << 3) }
+    }
+}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// A callback registered with <tt>on_reset</tt>, shared between a sketch and its clones.
+#[derive(Clone)]
+struct ResetHook(Arc<Mutex<ResetCallback>>);
+
+type ResetCallback = dyn FnMut(&FrequencyCountSketch) + Send;
+
+impl fmt::Debug for ResetHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResetHook")
+    }
 }
 
 impl FrequencyCountSketch {
 
     /// Initializes and increases the capacity of this <tt>FrequencySketch</tt> instance, if necessary,
     /// to ensure that it can accurately estimate the popularity of elements given the maximum size of
-    /// the cache. This operation forgets all previous counts when resizing.
+    /// the cache. This operation forgets all previous counts when resizing. The table length is the
+    /// maximum size rounded up to a power of two, but never less than <tt>MIN_TABLE_LEN</tt>; use
+    /// <tt>with_table_len</tt> to choose the length directly.
     pub fn new(maximum_size: usize) -> Self {
-        // 最大值，i32 / 2
-        let maximum = min(maximum_size, i32::MAX as usize >> 1);
-        let mut sample_size = 10usize;
-        if maximum > 0 {
-            sample_size = 10 * maximum;
+        let (table_len, maximum, sample_size) = dimensions_for(maximum_size);
+        Self::with_dimensions(table_len, maximum, sample_size)
+    }
+
+    /// Creates a sketch with an explicit table length instead of deriving it from the maximum size
+    /// of the cache. The length must be a power of two of at least 8, and the maximum size and
+    /// sample size are derived from it as if <tt>new(table_len)</tt> had been called.
+    pub fn with_table_len(table_len: usize) -> Result<Self, SketchError> {
+        if !table_len.is_power_of_two() || !(MIN_TABLE_LEN..=MAX_TABLE_LEN).contains(&table_len) {
+            return Err(SketchError::InvalidTableLength(table_len));
+        }
+        let maximum = min(table_len, i32::MAX as usize >> 1);
+        Ok(Self::with_dimensions(table_len, maximum, sample_size_for(maximum)))
+    }
+
+    /// Creates a sketch sized for a known number of distinct keys rather than a cache size, with the
+    /// smallest table for which the expected fraction of keys sharing at least one of their four
+    /// counters with another key, as measured by <tt>collision_estimate</tt>, is at most
+    /// <tt>target_collision_rate</tt>. The sample size is that of <tt>new(distinct)</tt>. Fails if
+    /// no supported table reaches the target, in particular for a target of 0 or below with more
+    /// than one key.
+    pub fn for_distinct_keys(distinct: usize, target_collision_rate: f64) -> Result<Self, SketchError> {
+        if target_collision_rate.is_nan() {
+            return Err(SketchError::UnreachableCollisionRate(target_collision_rate));
+        }
+        let mut table_len = MIN_TABLE_LEN;
+        while expected_collision_rate(table_len, distinct) > target_collision_rate {
+            if table_len == MAX_TABLE_LEN {
+                return Err(SketchError::UnreachableCollisionRate(target_collision_rate));
+            }
+            table_len <<= 1;
+        }
+        let maximum = min(distinct, i32::MAX as usize >> 1);
+        Ok(Self::with_dimensions(table_len, maximum, sample_size_for(maximum)))
+    }
+
+    /// Reconstructs a sketch for the given maximum size from counter values in the order returned by
+    /// <tt>decode_counters</tt>, e.g. to load a sketch serialized elsewhere. The number of counters must
+    /// match the table <tt>new(max_size)</tt> would build and every value must be at most 15. As the
+    /// sample accounting is not part of the counters, it is estimated from their total.
+    pub fn from_decoded(counters: &[u8], max_size: usize) -> Result<Self, SketchError> {
+        let (table_len, maximum, sample_size) = dimensions_for(max_size);
+        if counters.len() != table_len * 16 {
+            return Err(SketchError::InvalidCounterCount { expected: table_len * 16, actual: counters.len() });
+        }
+        if let Some((index, &value)) = counters.iter().enumerate().find(|(_, &c)| c > MAX_FREQUENCY) {
+            return Err(SketchError::CounterOutOfRange { index, value });
+        }
+        let mut sketch = Self::with_dimensions(table_len, maximum, sample_size);
+        for (word, chunk) in sketch.table.iter_mut().zip(counters.chunks(16)) {
+            *word = chunk.iter().enumerate().fold(0u64, |w, (n, &c)| w | (c as u64) << (n << 2));
+        }
+        sketch.estimate_size();
+        Ok(sketch)
+    }
+
+    /// Seeds a sketch for the given maximum size from a <tt>CountMinSketch64</tt> of the
+    /// <tt>count-min-sketch</tt> crate, e.g. when migrating a cache. The external sketch cannot
+    /// enumerate its keys, so only the provided keys are carried over, each incremented by its
+    /// external estimate as with <tt>increment_by</tt>. The conversion is lossy: estimates above 15
+    /// saturate, the external overestimates are kept and compounded by collisions here, and seeding
+    /// more than the sample size of increments ages the sketch along the way.
+    #[cfg(feature = "cms-adapter")]
+    pub fn from_count_min_sketch<K: Hash>(maximum_size: usize, source: &CountMinSketch64<K>, keys: &[K]) -> Self {
+        let mut sketch = Self::new(maximum_size);
+        for key in keys {
+            sketch.increment_by(key, min(source.estimate(key), MAX_FREQUENCY.into()) as u32);
         }
-        let table_len:usize = max(ceiling_power_of_two(maximum as i32), 8) as usize;
+        sketch
+    }
+
+    /// Return the table as raw bytes for persistence, e.g. through a memory-mapped file. The words
+    /// are in native byte order, so the bytes can only be loaded with <tt>from_byte_slice</tt> on a
+    /// host with the same endianness; use <tt>to_bytes</tt> for a portable encoding.
+    pub fn as_byte_slice(&self) -> &[u8] {
+        let len = self.table.len() * size_of::<u64>();
+        // SAFETY: u64 has no padding or invalid bit patterns and u8 has an alignment of 1, so the
+        // table's memory can be viewed as initialized bytes for as long as the table is borrowed
+        unsafe { std::slice::from_raw_parts(self.table.as_ptr() as *const u8, len) }
+    }
+
+    /// Reconstructs a sketch for the given maximum size from the native-endian bytes returned by
+    /// <tt>as_byte_slice</tt>. The number of bytes must match the table <tt>new(max_size)</tt> would
+    /// build. As for <tt>from_decoded</tt>, the sample accounting is estimated from the counters.
+    pub fn from_byte_slice(bytes: &[u8], max_size: usize) -> Result<Self, SketchError> {
+        Self::from_word_bytes(bytes, max_size, u64::from_ne_bytes)
+    }
+
+    /// Reverses the byte order of every table word in place. A table taken with
+    /// <tt>as_byte_slice</tt> on a host of the other endianness and loaded with
+    /// <tt>from_byte_slice</tt> reads every word byte-swapped, so calling this once after loading
+    /// restores the counters without a re-serialization through <tt>to_bytes</tt>. Calling it on a
+    /// table in the host's byte order scrambles the counters until it is called again.
+    pub fn byteswap_table(&mut self) {
+        for word in self.table.iter_mut() {
+            *word = word.swap_bytes();
+        }
+    }
+
+    /// Return the table in the canonical portable encoding: every word in little-endian byte order,
+    /// in table order. On little-endian hosts the bytes equal those of <tt>as_byte_slice</tt>, but
+    /// they are copied so that big-endian hosts can convert them. Load them with
+    /// <tt>from_bytes</tt>.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.table.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    /// Reconstructs a sketch for the given maximum size from the canonical little-endian bytes
+    /// returned by <tt>to_bytes</tt>, on a host of any endianness. The number of bytes must match the
+    /// table <tt>new(max_size)</tt> would build. As for <tt>from_decoded</tt>, the sample accounting
+    /// is estimated from the counters.
+    pub fn from_bytes(bytes: &[u8], max_size: usize) -> Result<Self, SketchError> {
+        Self::from_word_bytes(bytes, max_size, u64::from_le_bytes)
+    }
+
+    /// Reconstructs a sketch from table words encoded as 8 bytes each, decoded by <tt>decode</tt>.
+    fn from_word_bytes(bytes: &[u8], max_size: usize, decode: fn([u8; 8]) -> u64) -> Result<Self, SketchError> {
+        let (table_len, maximum, sample_size) = dimensions_for(max_size);
+        let expected = table_len * size_of::<u64>();
+        if bytes.len() != expected {
+            return Err(SketchError::InvalidByteLength { expected, actual: bytes.len() });
+        }
+        let mut sketch = Self::with_dimensions(table_len, maximum, sample_size);
+        for (word, chunk) in sketch.table.iter_mut().zip(bytes.chunks_exact(size_of::<u64>())) {
+            *word = decode(chunk.try_into().unwrap());
+        }
+        sketch.estimate_size();
+        Ok(sketch)
+    }
+
+    /// Return the table words, e.g. to snapshot the sketch with a custom serialization.
+    pub fn raw_table(&self) -> &[u64] {
+        &self.table
+    }
+
+    /// Reconstructs a sketch from table words taken with <tt>raw_table</tt>, the maximum size it was
+    /// created for and its sample accounting from <tt>estimated_cardinality</tt>. Only the table
+    /// length is validated, which must be a power of two of at least 8; the words are copied as is
+    /// into a newly allocated, cache-line aligned table.
+    pub fn from_raw_parts(table: &[u64], max_size: usize, size: usize) -> Result<Self, SketchError> {
+        let maximum = min(max_size, i32::MAX as usize >> 1);
+        let mut sketch = Self::from_raw(table, maximum, sample_size_for(maximum), 0)?;
+        sketch.size = size;
+        Ok(sketch)
+    }
+
+    /// Reconstructs a sketch from table words and its complete sample accounting, e.g. to set up a
+    /// specific table state in a test or to load a table decoded elsewhere. The table length must
+    /// be a power of two of at least 8, from which the block mask is derived, and the size must not
+    /// exceed the non-zero sample size. The counters themselves are not validated, and the words are
+    /// copied into a newly allocated, cache-line aligned table.
+    pub fn from_raw(
+        table: &[u64],
+        max_size: usize,
+        sample_size: usize,
+        size: usize,
+    ) -> Result<Self, SketchError> {
+        let table_len = table.len();
+        if !table_len.is_power_of_two() || !(MIN_TABLE_LEN..=MAX_TABLE_LEN).contains(&table_len) {
+            return Err(SketchError::InvalidTableLength(table_len));
+        }
+        if sample_size == 0 || size > sample_size {
+            return Err(SketchError::CorruptState(format!(
+                "size {} is not within a sample size of {}",
+                size, sample_size
+            )));
+        }
+        let mut sketch = Self::with_table(Table::from_words(table), max_size, sample_size);
+        sketch.size = size;
+        Ok(sketch)
+    }
+
+    /// Return the non-zero table words as <tt>(word_index, word)</tt> pairs, a compact checkpoint of a
+    /// lightly populated sketch that <tt>apply_sparse</tt> restores.
+    pub fn to_sparse(&self) -> Vec<(u32, u64)> {
+        self.table
+            .iter()
+            .enumerate()
+            .filter(|(_, &w)| w != 0)
+            .map(|(i, &w)| (i as u32, w))
+            .collect()
+    }
+
+    /// Overwrites the table words listed in a checkpoint produced by <tt>to_sparse</tt> on a sketch
+    /// of the same dimensions, leaving other words unchanged. The sample accounting is raised to
+    /// cover the restored counters if needed. Fails without modifying the sketch if a word index is
+    /// outside the table.
+    pub fn apply_sparse(&mut self, entries: &[(u32, u64)]) -> Result<(), SketchError> {
+        if let Some(&(index, _)) = entries.iter().find(|(i, _)| *i as usize >= self.table_len) {
+            return Err(SketchError::WordIndexOutOfRange(index as usize));
+        }
+        for &(index, word) in entries {
+            self.table[index as usize] = word;
+        }
+        self.estimate_size();
+        Ok(())
+    }
+
+    /// Estimates the sample accounting of a restored table from its total counter mass, since each
+    /// counted increment raises at most four counters. The accounting is never lowered.
+    fn estimate_size(&mut self) {
+        let mass = self.total_mass() as usize;
+        self.size = max(self.size, min(mass.div_ceil(4), self.sample_size - 1));
+    }
+
+    /// Creates an empty sketch from already validated dimensions.
+    fn with_dimensions(table_len: usize, max_size: usize, sample_size: usize) -> Self {
+        Self::with_table(Table::zeroed(table_len), max_size, sample_size)
+    }
+
+    /// Creates a sketch around a table whose length is already validated.
+    fn with_table(table: Table, max_size: usize, sample_size: usize) -> Self {
+        let table_len = table.len();
         Self {
             sample_size,
+            block_shift: DEFAULT_BLOCK_WORDS.trailing_zeros(),
             block_mask: (table_len >> 3) - 1,
-            table: vec![0; table_len],
+            table,
             table_len,
             size: 0,
-            max_size: maximum,
+            max_size,
+            aging_enabled: true,
+            aggressive_aging: false,
+            reset_hook: None,
+            adaptive_bounds: None,
+            incremental_reset: false,
+            reset_sweep: None,
+            independent_rows: false,
+            decay_factor: None,
+            hash_seed: None,
+            reset_count: 0,
+            hash_run: (0, 0),
+            events: 0,
+            warmup: sample_size as u64,
+            #[cfg(feature = "std")]
+            elapsed: Duration::ZERO,
+            #[cfg(feature = "std")]
+            reset_period: None,
+            #[cfg(feature = "std")]
+            last_instant: None,
+            #[cfg(feature = "distinct-count")]
+            distinct: DistinctCounter::new(),
+        }
+    }
+
+    /// Initializes a sketch like <tt>new</tt> whose <tt>reset</tt> multiplies every counter by
+    /// <tt>decay</tt> instead of halving it, smoothing the aging into an exponential moving average.
+    /// The factor is approximated in fixed point as a multiple of 1/256 and the scaled counters are
+    /// rounded down, so a counter of 1 always decays to 0 and a factor of 0.5 is exactly the default
+    /// halving. The sample accounting is scaled by the same factor. Aggressive aging does not apply
+    /// to a decaying sketch, except for a factor that rounds to 0.5, which is kept as the default
+    /// halving and so behaves exactly like a sketch from <tt>new</tt>, aggressive aging included.
+    /// The decay must lie strictly between 0 and 1.
+    pub fn new_with_decay(maximum_size: usize, decay: f32) -> Result<Self, SketchError> {
+        if !(decay > 0.0 && decay < 1.0) {
+            return Err(SketchError::InvalidDecay(decay));
+        }
+        let factor = ((decay * 256.0).round() as u64).clamp(1, 255);
+        let mut sketch = Self::new(maximum_size);
+        if factor != 128 {
+            sketch.decay_factor = Some(factor);
+        }
+        Ok(sketch)
+    }
+
+    /// Initializes a sketch like <tt>new</tt> that places the elements with SipHash-1-3 keyed by
+    /// <tt>seed</tt> instead of the fixed default hasher. When the keys are attacker controlled, e.g.
+    /// URLs or user names, the fixed placement lets an attacker precompute keys that share counters
+    /// with a victim key and inflate its estimate, or flood a single block. A secret seed makes the
+    /// placement unpredictable without it. The seed is not part of the serialized forms, so a sketch
+    /// restored from them must be seeded again to be queried consistently.
+    pub fn new_with_seed(maximum_size: usize, seed: u64) -> Self {
+        let mut sketch = Self::new(maximum_size);
+        sketch.hash_seed = Some(seed);
+        sketch
+    }
+
+    /// Initializes a sketch like <tt>new</tt> that reports itself warm, see <tt>is_warm</tt>, once
+    /// it has counted <tt>warmup</tt> increments instead of the sample size.
+    pub fn new_with_warmup(maximum_size: usize, warmup: u64) -> Self {
+        let mut sketch = Self::new(maximum_size);
+        sketch.warmup = warmup;
+        sketch
+    }
+
+    /// Initializes a sketch like <tt>new</tt> whose sample size tunes itself to the workload. Every
+    /// reset measures the saturation ratio, the fraction of counters that are non-zero just before
+    /// halving. A ratio above 0.5 means the table is crowded and collisions inflate the estimates, so
+    /// the sample window is halved to age more often; a ratio below 0.25 means the table is sparse,
+    /// so the window is doubled to retain more history. The sample size always stays within a
+    /// quarter and four times the sample size <tt>new</tt> would use.
+    pub fn new_adaptive(maximum_size: usize) -> Self {
+        let mut sketch = Self::new(maximum_size);
+        sketch.adaptive_bounds = Some(adaptive_bounds(sketch.sample_size));
+        sketch
+    }
+
+    /// Initializes a sketch like <tt>new</tt> whose four depths are indexed by independent hash
+    /// functions. By default the four counters of an element are sliced from a single hash and share
+    /// one 64-byte block, so keys that land in the same block collide far more often than a true
+    /// count-min sketch would allow, and an adversary can target a key through its block. In this
+    /// mode the hash code is rehashed with a distinct seed for each depth, which selects a block of
+    /// its own, so errors are uncorrelated across depths. Each depth still owns its quarter of every
+    /// block, so depths never share counters. The cost is four extra hash computations and up to four
+    /// cache misses per operation.
+    pub fn new_independent(maximum_size: usize) -> Self {
+        let mut sketch = Self::new(maximum_size);
+        sketch.independent_rows = true;
+        sketch
+    }
+
+    /// Initializes a sketch like <tt>new</tt> whose element counters are confined to blocks of
+    /// <tt>block_words</tt> table words instead of <tt>DEFAULT_BLOCK_WORDS</tt>, e.g. 16 words to fill
+    /// the 128-byte cache lines of some ARM and POWER processors. Each of the four depths owns a
+    /// quarter of the block, so larger blocks spread the counters over more words and collide less.
+    /// The block length must be a power of two between 8 and <tt>MAX_BLOCK_WORDS</tt>, and the table
+    /// is grown to hold at least one block. Blocks longer than 8 words are only 64-byte aligned.
+    pub fn with_block_words(maximum_size: usize, block_words: usize) -> Result<Self, SketchError> {
+        if !block_words.is_power_of_two() || !(DEFAULT_BLOCK_WORDS..=MAX_BLOCK_WORDS).contains(&block_words) {
+            return Err(SketchError::InvalidBlockWords(block_words));
+        }
+        let (table_len, maximum, sample_size) = dimensions_for(maximum_size);
+        let mut sketch = Self::with_dimensions(max(table_len, block_words), maximum, sample_size);
+        sketch.block_shift = block_words.trailing_zeros();
+        sketch.block_mask = (sketch.table_len >> sketch.block_shift) - 1;
+        Ok(sketch)
+    }
+
+    /// Shrinks the table when the number of distinct keys actually observed is far below the maximum
+    /// size the sketch was created for, so that the table would be at least four times smaller. The
+    /// sketch is rebuilt as if created by <tt>new(observed_distinct)</tt>, which forgets all previous
+    /// counts, while keeping its aging settings. Return whether compaction occurred.
+    pub fn compact(&mut self, observed_distinct: usize) -> bool {
+        let (table_len, maximum, sample_size) = dimensions_for(observed_distinct);
+        let table_len = max(table_len, 1 << self.block_shift);
+        if table_len << 2 > self.table_len {
+            return false;
+        }
+        self.rebuild(table_len, maximum, sample_size);
+        true
+    }
+
+    /// Shrinks the table to that of <tt>new(new_maximum_size)</tt> while carrying over the counts of
+    /// the given heavy hitters, e.g. the keys <tt>rank</tt> puts first among the cache's entries.
+    /// The sketch cannot enumerate its keys, so every key that is not listed, in particular the
+    /// tail of the distribution, is lost. The listed keys are re-counted with their current
+    /// estimates, which also carries over any overestimate from collisions in the larger table. The
+    /// aging settings are kept. Return whether the table shrank; nothing changes if the new table
+    /// would not be smaller. If a reset hook panics while the keys are re-counted, the sketch is
+    /// left valid and usable with only part of the counts carried over.
+    pub fn shrink_to<E: Hash>(&mut self, new_maximum_size: usize, heavy_hitters: &[E]) -> bool {
+        let (table_len, maximum, sample_size) = dimensions_for(new_maximum_size);
+        let table_len = max(table_len, 1 << self.block_shift);
+        if table_len >= self.table_len {
+            return false;
+        }
+        let counts: Vec<(u64, u8)> = heavy_hitters
+            .iter()
+            .map(|e| {
+                let hash_code = self.hash_code(e);
+                (hash_code, self.frequency_hashed(hash_code))
+            })
+            .collect();
+        self.rebuild(table_len, maximum, sample_size);
+        for (hash_code, count) in counts {
+            for _ in 0..count {
+                self.increment_hash_code(hash_code);
+            }
+        }
+        true
+    }
+
+    /// Replaces the table with an empty one of the given dimensions, keeping the block layout and
+    /// aging settings. The new table is allocated before any field changes, so a failed allocation
+    /// cannot leave the dimensions and the table out of step.
+    fn rebuild(&mut self, table_len: usize, maximum: usize, sample_size: usize) {
+        self.table = Table::zeroed(table_len);
+        self.table_len = table_len;
+        self.block_mask = (table_len >> self.block_shift) - 1;
+        self.max_size = maximum;
+        self.sample_size = sample_size;
+        self.size = 0;
+        self.reset_sweep = None;
+        if self.adaptive_bounds.is_some() {
+            self.adaptive_bounds = Some(adaptive_bounds(sample_size));
+        }
+    }
+
+    /// Empties the sketch as if it had just been created with its current dimensions and settings,
+    /// zeroing the table in place rather than reallocating it, e.g. to reuse a short-lived sketch.
+    /// The reset hook is kept, while a pending incremental reset, the time accumulated towards the
+    /// next wall-clock reset and the self-tuned sample size of an adaptive sketch are discarded.
+    pub fn clear(&mut self) {
+        self.table.fill(0);
+        self.size = 0;
+        self.reset_sweep = None;
+        self.reset_count = 0;
+        self.hash_run = (0, 0);
+        self.events = 0;
+        if self.adaptive_bounds.is_some() {
+            self.sample_size = sample_size_for(self.max_size);
+        }
+        #[cfg(feature = "std")]
+        {
+            self.elapsed = Duration::ZERO;
+            self.last_instant = None;
+        }
+        #[cfg(feature = "distinct-count")]
+        {
+            self.distinct = DistinctCounter::new();
+        }
+    }
+
+    /// Return the accumulated counts and leaves this sketch empty, e.g. to read the frequencies of a
+    /// metrics interval and start the next one. The returned snapshot is the sketch as it was, while
+    /// this sketch gets a zeroed table of the same dimensions and keeps its settings and reset hook.
+    /// A pending incremental reset stays with the snapshot. Unlike cloning and clearing, the table
+    /// is moved rather than copied.
+    pub fn drain(&mut self) -> FrequencyCountSketch {
+        let empty = Self {
+            table: Table::zeroed(self.table_len),
+            size: 0,
+            reset_hook: self.reset_hook.clone(),
+            reset_sweep: None,
+            reset_count: 0,
+            hash_run: (0, 0),
+            events: 0,
+            #[cfg(feature = "distinct-count")]
+            distinct: DistinctCounter::new(),
+            ..*self
+        };
+        mem::replace(self, empty)
+    }
+
+    /// Enables or disables aggressive aging. By default <tt>reset</tt> only halves the counters, so a
+    /// key that stays hot pins its counters at 15 and a formerly hot key needs several resets before
+    /// newer keys can outrank it. In aggressive mode every saturated counter is additionally reduced
+    /// by 4 after halving (15 becomes 3 rather than 7), letting long-lived keys lose rank quickly.
+    /// The tradeoff is that keys which are still hot are also under-ranked for a short while after
+    /// each reset, until their counters climb back.
+    pub fn set_aggressive_aging(&mut self, enabled: bool) {
+        self.aggressive_aging = enabled;
+    }
+
+    /// Enables or disables aging by event count. With aging disabled, reaching the sample size never
+    /// resets the sketch, so counters keep their exact-ish counts until they saturate at 15, e.g. to
+    /// count a short, bounded stream or a fixed window that is discarded afterwards. Explicit calls to
+    /// <tt>reset</tt>, <tt>decay</tt> and <tt>maybe_reset_after</tt> still age the sketch. Additions are
+    /// still counted up to the sample size, so the next addition after re-enabling aging resets the
+    /// sketch if the sample size has been reached in the meantime.
+    pub fn set_aging_enabled(&mut self, enabled: bool) {
+        self.aging_enabled = enabled;
+    }
+
+    /// Sets the period of the wall-clock driven resets performed by <tt>increment_at_time</tt>, or
+    /// disables them with <tt>None</tt>, which is the default.
+    ///
+    /// Panics if <tt>period</tt> is zero.
+    #[cfg(feature = "std")]
+    pub fn set_reset_period(&mut self, period: Option<Duration>) {
+        assert!(period != Some(Duration::ZERO), "reset period must be positive");
+        self.reset_period = period;
+    }
+
+    /// Enables or disables incremental resets. A reset is O(table_len), so by default the increment
+    /// that reaches the sample size pays for aging the whole table. In incremental mode reaching the
+    /// sample size only starts a sweep, and each following increment ages the next 8 words until
+    /// the whole table has been halved exactly once, after which the sample accounting is updated
+    /// and the reset callback runs. Counts added during the sweep are halved if they land ahead of
+    /// the sweep and kept whole otherwise. Disabling the mode completes a pending sweep.
+    pub fn set_incremental_reset(&mut self, enabled: bool) {
+        self.incremental_reset = enabled;
+        if !enabled && self.reset_sweep.is_some() {
+            self.reset();
         }
     }
 
+    /// Return whether an incremental reset has been started and not yet aged the whole table.
+    pub fn reset_in_progress(&self) -> bool {
+        self.reset_sweep.is_some()
+    }
+
+    /// Registers a callback invoked at the end of every <tt>reset</tt> or <tt>decay</tt> with a
+    /// reference to the aged sketch, replacing any previously registered one. Clones of the sketch
+    /// share the callback. The callback must be <tt>Send</tt> so that the sketch stays <tt>Send</tt>
+    /// and <tt>Sync</tt>; state it updates is shared through e.g. <tt>Arc</tt> and <tt>Mutex</tt>
+    /// rather than <tt>Rc</tt> and <tt>RefCell</tt>.
+    pub fn on_reset<F: FnMut(&FrequencyCountSketch) + Send + 'static>(&mut self, hook: F) {
+        self.reset_hook = Some(ResetHook(Arc::new(Mutex::new(hook))));
+    }
+
+    /// Unregisters the reset callback, if any, e.g. before a recycled sketch is handed to a new owner.
+    pub(crate) fn remove_reset_hook(&mut self) {
+        self.reset_hook = None;
+    }
+
     /// Return max size of this sketch
     pub fn get_max_size(&self) -> usize {
         self.max_size
@@ -50,89 +680,1495 @@ impl FrequencyCountSketch {
         self.table_len
     }
 
+    /// Return the mask selecting a 64-byte block of the table from a spread hash.
+    pub fn block_mask(&self) -> usize {
+        self.block_mask
+    }
+
+    /// Return the number of table words in the block holding an element's counters.
+    pub fn block_words(&self) -> usize {
+        1 << self.block_shift
+    }
+
+    /// Return the number of counter-changing increments after which the sketch ages.
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+
+    /// Return the maximum number of cache entries this sketch was sized for.
+    pub fn capacity(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return the total number of 4-bit counters held by the table.
+    pub fn counter_count(&self) -> usize {
+        self.table_len * 16
+    }
+
+    /// Return the largest estimated frequency this sketch can report, e.g. to normalize estimates
+    /// without hardcoding the counter width. This is <tt>MAX_FREQUENCY</tt> for every sketch today.
+    pub fn max_frequency(&self) -> u8 {
+        MAX_FREQUENCY
+    }
+
+    /// Return the number of bytes used by the counter table.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.table_len * size_of::<u64>()
+    }
+
     /// Return the estimated number of occurrences of an element, up to the maximum (15).
     pub fn frequency<E: Hash>(&self, e: E) -> u8 {
-        let mut count:[u8; 4] = [0; 4];
-        let hash_code = default_hash_code(e);
-        let block_hash = self.spread(hash_code);
-        let counter_hash = self.rehash(block_hash);
-        let block = (block_hash & self.block_mask) << 3;
-        for i in 0..4 {
-            let h = counter_hash >> (i << 3);
-            let index = (h >> 1) & 15;
-            let offset = h & 1;
-            count[i] = ((self.table[block + offset + (i << 1)] >> (index << 2)) & 0xf) as u8;
+        self.frequency_hashed(self.hash_code(e))
+    }
+
+    /// Return the estimated frequency of an element like <tt>frequency</tt>, for diagnostic paths that
+    /// must make their intent explicit. Peeking is guaranteed to be free of side effects: it never
+    /// counts as an access, advances an incremental reset or affects aging.
+    pub fn peek<E: Hash>(&self, e: E) -> u8 {
+        self.frequency(e)
+    }
+
+    /// Return the estimated number of occurrences of the element with the given
+    /// <tt>hash_code</tt>, so that a key hashed once can be looked up in several sketches.
+    pub fn frequency_hashed(&self, hash_code: u64) -> u8 {
+        self.frequency_from_block_hash(spread(hash_code))
+    }
+
+    /// Return the estimated number of occurrences of a key that is already a well-distributed 64-bit
+    /// fingerprint, counted with <tt>increment_u64</tt>. The key is used as its own hash code, as
+    /// with <tt>frequency_hashed</tt>, skipping the default hasher and ignoring any seed.
+    pub fn frequency_u64(&self, key: u64) -> u8 {
+        self.frequency_hashed(key)
+    }
+
+    /// Return the estimated number of occurrences of the element whose hash code has already been
+    /// passed through <tt>spread</tt>, the first step of every lookup. The caller is responsible for
+    /// applying <tt>spread(hash_code(e))</tt>, which lets the value be cached when the same
+    /// key is probed repeatedly or across several sketches.
+    pub fn frequency_from_block_hash(&self, block_hash: usize) -> u8 {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("avx2") {
+            let (words, nibbles) = self.positions(block_hash);
+            self.prefetch(&words);
+            // SAFETY: AVX2 support was detected at runtime
+            return unsafe { min_counter_avx2(&self.table, &words, &nibbles) };
         }
+        let count = self.counters(block_hash);
         min(min(count[0], count[1]), min(count[2], count[3]))
     }
 
+    /// Return whether the element has a non-zero estimate in the current aging window, i.e. it was
+    /// incremented since its counters were last aged to zero. This is not lifetime presence: a
+    /// <tt>reset</tt> halves the counters, so a key seen only once drops to 0 and reports false even
+    /// if it used to be frequent. Collisions may report a key that was never incremented.
+    pub fn seen_this_window<E: Hash>(&self, e: E) -> bool {
+        self.is_heavy_hitter(e, 1)
+    }
+
+    /// Return whether the estimated frequency of the element is at least the threshold, e.g. for
+    /// admission or throttling decisions. Equivalent to <tt>frequency(e) >= threshold</tt>, but stops
+    /// at the first counter below the threshold.
+    pub fn is_heavy_hitter<E: Hash>(&self, e: E, threshold: u8) -> bool {
+        self.counters(spread(self.hash_code(e))).iter().all(|&c| c >= threshold)
+    }
+
+    /// Return the candidates whose estimated frequency is at least the threshold, in their input
+    /// order, e.g. as the input to an admission, eviction or promotion decision. See
+    /// <tt>is_heavy_hitter</tt>.
+    pub fn filter_heavy<E: Hash + Clone>(&self, candidates: &[E], threshold: u8) -> Vec<E> {
+        candidates.iter().filter(|&e| self.is_heavy_hitter(e, threshold)).cloned().collect()
+    }
+
+    /// Return the keys whose estimated frequency meets or exceeds the threshold, preserving their
+    /// input order. Equivalent to <tt>filter_heavy</tt>.
+    ///
+    /// ```
+    /// use rust_frequency_counter::sketch::frequency_count_sketch::FrequencyCountSketch;
+    ///
+    /// let mut sketch = FrequencyCountSketch::new(512);
+    /// sketch.increment_by("hot", 3);
+    /// sketch.increment_by("warm", 2);
+    /// sketch.increment("cold");
+    /// assert_eq!(sketch.above_threshold(&["cold", "warm", "hot"], 2), ["warm", "hot"]);
+    /// ```
+    pub fn above_threshold<E: Hash + Clone>(&self, keys: &[E], threshold: u8) -> Vec<E> {
+        self.filter_heavy(keys, threshold)
+    }
+
+    /// Return the smallest non-zero estimated frequency among the keys, i.e. the popularity of the
+    /// least popular key the sketch is tracking, e.g. to adapt an admission threshold. Keys estimated
+    /// at zero are ignored, so this is <tt>None</tt> when there are no keys or none was seen.
+    pub fn min_frequency<E: Hash, I: IntoIterator<Item = E>>(&self, keys: I) -> Option<u8> {
+        keys.into_iter().map(|e| self.frequency(e)).filter(|&f| f > 0).min()
+    }
+
+    /// Compares the estimated frequencies of two keys, e.g. to sort candidate keys by popularity.
+    pub fn compare<A: Hash, B: Hash>(&self, a: A, b: B) -> Ordering {
+        self.frequency(a).cmp(&self.frequency(b))
+    }
+
+    /// Return the candidate keys paired with their estimated frequencies, sorted by descending
+    /// frequency, e.g. to pick eviction victims among the keys currently in a cache from the end of
+    /// the list. Keys with equal estimates keep their order in the input.
+    pub fn rank<E: Hash + Clone>(&self, keys: &[E]) -> Vec<(E, u8)> {
+        let mut ranked: Vec<(E, u8)> = keys.iter().map(|k| (k.clone(), self.frequency(k))).collect();
+        ranked.sort_by_key(|&(_, frequency)| Reverse(frequency));
+        ranked
+    }
+
+    /// Return the values of the element's four counters, one per depth of the count-min sketch, before
+    /// taking their minimum. An isolated element has all four equal to its count, while collisions
+    /// raise some of them.
+    pub fn counters_for<E: Hash>(&self, e: E) -> [u8; DEPTH] {
+        self.counters(spread(self.hash_code(e)))
+    }
+
+    /// Return an estimate of the number of occurrences of an element corrected for collision noise,
+    /// following the count-mean-min estimator. Each of the element's counters is reduced by the
+    /// noise expected from the other keys of its row, the row's mass excluding the counter spread
+    /// evenly over the row's other counters, and the median of the corrected readings is taken,
+    /// never exceeding <tt>frequency</tt>. On heavy-tailed streams, where the many rare keys inflate
+    /// every counter a little, this removes much of the overestimation of the minimum, at the cost
+    /// of sometimes underestimating. This scans the whole table.
+    pub fn frequency_cmm<E: Hash>(&self, e: E) -> f64 {
+        let row_shift = self.block_shift - 2;
+        let mut row_mass = [0u64; DEPTH];
+        for (i, &word) in self.table.iter().enumerate() {
+            row_mass[(i >> row_shift) & 3] += nibble_sum_word(word) as u64;
+        }
+        count_mean_min(self.counters_for(e), row_mass, [self.counter_count() / DEPTH; DEPTH])
+    }
+
+    /// Return the largest of the element's four counters, an upper bound of its estimate. The gap to
+    /// <tt>frequency</tt> indicates how much collisions with other elements inflate its counters.
+    pub fn frequency_upper<E: Hash>(&self, e: E) -> u8 {
+        let count = self.counters_for(e);
+        max(max(count[0], count[1]), max(count[2], count[3]))
+    }
+
+    /// Return the median of the element's four counters, the lower of the two middle values. The
+    /// minimum used by <tt>frequency</tt> is the best estimate when counters only ever grow, but an
+    /// incremental reset ages a long block's counters at different times, and the minimum then
+    /// follows the counter aged first; the median ignores the single outlier on either side. It is
+    /// more exposed to collisions, so <tt>frequency</tt> remains the default.
+    pub fn frequency_median<E: Hash>(&self, e: E) -> u8 {
+        let mut count = self.counters_for(e);
+        count.sort_unstable();
+        count[1]
+    }
+
+    /// Return the estimated frequency of an element together with a confidence score in [0, 1]. The
+    /// score is the ratio of the smallest to the largest of the element's four counters, which is 1.0
+    /// when all four agree (or are all zero) and drops as collisions with other elements inflate some
+    /// of the counters.
+    pub fn frequency_scored<E: Hash>(&self, e: E) -> (u8, f64) {
+        let count = self.counters_for(e);
+        let lowest = min(min(count[0], count[1]), min(count[2], count[3]));
+        let highest = max(max(count[0], count[1]), max(count[2], count[3]));
+        if highest == 0 {
+            return (0, 1.0);
+        }
+        (lowest, lowest as f64 / highest as f64)
+    }
+
+    /// Return the count-min error bound relative to the total counter mass. A count-min sketch of
+    /// rows of <tt>w</tt> counters overestimates a key by at most <tt>e / w</tt> times the events
+    /// counted with probability <tt>1 - e^-DEPTH</tt>, and every event adds to all rows, so this is
+    /// <tt>e / w</tt> divided by the number of rows. Sharing one block across the rows makes the
+    /// guarantee approximate.
+    pub fn error_bound(&self) -> f64 {
+        std::f64::consts::E / self.counter_count() as f64
+    }
+
+    /// Return the estimated frequency of an element together with the count-min upper bound of its
+    /// count, <tt>frequency + ceil(error_bound * total_mass)</tt>. The estimate is the lower end of
+    /// the interval: counters only ever overestimate, so the true count of the current window lies
+    /// within the interval with high probability, except for counts lost to saturation. The
+    /// interval widens as the sketch fills. This scans the whole table.
+    pub fn frequency_interval<E: Hash>(&self, e: E) -> (u8, u32) {
+        let estimate = self.frequency(e);
+        // At most e * 15, as the mass never exceeds 15 per counter
+        let noise = (self.error_bound() * self.total_mass() as f64).ceil() as u32;
+        (estimate, estimate as u32 + noise)
+    }
+
+    /// Return whether this sketch and another estimate the frequency of the key within
+    /// <tt>tolerance</tt> of each other, e.g. to compare a sketch with its merged, decayed or
+    /// deserialized counterpart where exact equality is too strict.
+    pub fn frequencies_close<E: Hash>(&self, other: &FrequencyCountSketch, key: E, tolerance: u8) -> bool {
+        let hash_code = self.hash_code(key);
+        self.frequency_hashed(hash_code).abs_diff(other.frequency_hashed(hash_code)) <= tolerance
+    }
+
+    /// Return the spread between the largest and the smallest of the element's four counters, a cheap
+    /// proxy for how much collision noise its counters carry. An element whose counters are not
+    /// shared reports 0, while collisions raise some of its counters above the others.
+    pub fn overcount_estimate<E: Hash>(&self, e: E) -> u8 {
+        let count = self.counters_for(e);
+        let lowest = min(min(count[0], count[1]), min(count[2], count[3]));
+        let highest = max(max(count[0], count[1]), max(count[2], count[3]));
+        highest - lowest
+    }
+
+    /// Return the estimated number of occurrences of a raw byte key counted with
+    /// <tt>increment_bytes</tt>, up to the maximum (15).
+    pub fn frequency_bytes(&self, bytes: &[u8]) -> u8 {
+        self.frequency_hashed(self.hash_bytes(bytes))
+    }
+
+    /// Return the estimated number of occurrences of a key counted with <tt>increment_key</tt>, up
+    /// to the maximum (15). The key is hashed by its byte representation, so a <tt>&str</tt>, a
+    /// <tt>String</tt> and a <tt>Vec&lt;u8&gt;</tt> holding the same bytes are the same key.
+    pub fn frequency_key(&self, key: impl AsRef<[u8]>) -> u8 {
+        self.frequency_bytes(key.as_ref())
+    }
+
+    /// Return the estimated frequency of an element and whether it is clamped at the maximum (15),
+    /// i.e. all four of its counters are saturated and the true count may be higher.
+    pub fn frequency_saturating<E: Hash>(&self, e: E) -> (u8, bool) {
+        let count = self.counters_for(e);
+        let frequency = min(min(count[0], count[1]), min(count[2], count[3]));
+        (frequency, frequency == MAX_FREQUENCY)
+    }
+
+    /// Return the estimated frequency of an element normalized to [0, 1] by the counter maximum (15).
+    pub fn relative_frequency<E: Hash>(&self, e: E) -> f64 {
+        self.frequency(e) as f64 / MAX_FREQUENCY as f64
+    }
+
+    /// Return the estimated frequency of an element normalized to [0, 1] by the largest counter
+    /// currently held by the table, or 0 when the table is empty. This scans the whole table.
+    pub fn relative_to_peak<E: Hash>(&self, e: E) -> f64 {
+        let peak = self.decoded().max().unwrap_or(0);
+        if peak == 0 {
+            return 0.0;
+        }
+        self.frequency(e) as f64 / peak as f64
+    }
+
+    /// Return the number of counters holding each value from 0 to 15. This scans the whole table.
+    pub fn histogram(&self) -> [usize; 16] {
+        let mut histogram = [0usize; 16];
+        for c in self.decoded() {
+            histogram[c as usize] += 1;
+        }
+        histogram
+    }
+
+    /// Return the indices of the <tt>n</tt> blocks holding the most counter mass together with their
+    /// mass, sorted by decreasing mass and then by index, e.g. to find the key clusters dominating
+    /// the table. Block <tt>i</tt> spans the words from <tt>i * block_words</tt>. This scans the
+    /// whole table.
+    pub fn hottest_blocks(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut blocks: Vec<(usize, u64)> = self
+            .table
+            .chunks(self.block_words())
+            .map(|block| block.iter().map(|&w| nibble_sum_word(w) as u64).sum())
+            .enumerate()
+            .collect();
+        blocks.sort_by_key(|&(i, mass)| (Reverse(mass), i));
+        blocks.truncate(n);
+        blocks
+    }
+
+    /// Iterates over the indices of the blocks holding at least one non-zero counter, in increasing
+    /// order, e.g. to export or inspect a sparse sketch without visiting its empty regions. Each
+    /// block is skipped as soon as one of its words is found non-zero.
+    pub fn nonempty_blocks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.table
+            .chunks(self.block_words())
+            .enumerate()
+            .filter(|(_, block)| block.iter().any(|&w| w != 0))
+            .map(|(i, _)| i)
+    }
+
+    /// Return the fraction of the counter mass, the sum of all counter values, held by counters at
+    /// or below the element's estimate, as an approximation of its popularity percentile. This is
+    /// computed over the counters rather than over keys: each key contributes to four counters and
+    /// collisions merge keys, so the result only orders keys roughly. A saturated key scores 1.0
+    /// and an empty sketch scores 0 for every key. This scans the whole table.
+    pub fn percentile<E: Hash>(&self, e: E) -> f64 {
+        let histogram = self.histogram();
+        let mass = |values: &[usize]| values.iter().enumerate().map(|(v, &n)| v * n).sum::<usize>();
+        let total = mass(&histogram);
+        if total == 0 {
+            return 0.0;
+        }
+        mass(&histogram[..=self.frequency(e) as usize]) as f64 / total as f64
+    }
+
+    /// Return the fraction of the population whose estimated frequency is lower than or equal to
+    /// the element's, a rank score between 0 and 1 that is comparable across differently loaded
+    /// sketches. Unlike <tt>percentile</tt>, it ranks the element among actual keys. The element
+    /// need not belong to the population, and an empty population scores 0.
+    pub fn percentile_among<E: Hash>(&self, e: E, population: &[E]) -> f64 {
+        if population.is_empty() {
+            return 0.0;
+        }
+        let estimate = self.frequency(e);
+        let at_or_below = population.iter().filter(|key| self.frequency(key) <= estimate).count();
+        at_or_below as f64 / population.len() as f64
+    }
+
+    /// Return the number of completed resets since the sketch was created or drained. Every aging of
+    /// the whole table counts, including <tt>decay</tt>, <tt>reset_weighted</tt> and
+    /// <tt>decay_to_mass</tt>.
+    pub fn reset_count(&self) -> u64 {
+        self.reset_count
+    }
+
+    /// Return the sample accounting as <tt>(size, sample_size, reset_count)</tt>, a lightweight
+    /// checkpoint for a sketch whose table is rebuilt from elsewhere, e.g. by replaying a log.
+    /// <tt>restore_accounting</tt> restores it.
+    pub fn accounting_snapshot(&self) -> (usize, usize, u64) {
+        (self.size, self.sample_size, self.reset_count)
+    }
+
+    /// Restores the sample accounting from a checkpoint produced by <tt>accounting_snapshot</tt>,
+    /// leaving the table unchanged. Fails without modifying the sketch if the sample size is 0 or
+    /// the size exceeds it. Any other values are accepted: every later update of the accounting
+    /// saturates, the size at the sample size and the reset count at <tt>u64::MAX</tt>, so even
+    /// extreme checkpoints cannot overflow.
+    pub fn restore_accounting(
+        &mut self,
+        size: usize,
+        sample_size: usize,
+        reset_count: u64,
+    ) -> Result<(), SketchError> {
+        if sample_size == 0 || size > sample_size {
+            return Err(SketchError::CorruptState(format!(
+                "size {} is not within a sample size of {}",
+                size, sample_size
+            )));
+        }
+        self.size = size;
+        self.sample_size = sample_size;
+        self.reset_count = reset_count;
+        Ok(())
+    }
+
+    /// Return a rough approximation of the element's absolute number of occurrences over the
+    /// lifetime of the sketch, undoing the aging by scaling its estimate by the factor applied at
+    /// every reset: 2 for halving, or the inverse of the decay factor. The approximation is heavy.
+    /// The sketch cannot tell when a key was first seen, so every reset since creation is undone,
+    /// which overstates keys that appeared recently; the estimate is capped at 15 before scaling
+    /// and truncated by every reset, and a key aged to zero scores 0 however popular it once was.
+    /// Explicit agings such as <tt>decay</tt> count as resets and are undone by the same factor,
+    /// whatever fraction they actually removed.
+    pub fn estimate_absolute<E: Hash>(&self, e: E) -> f64 {
+        let per_reset = match self.decay_factor {
+            Some(factor) => 256.0 / factor as f64,
+            None => 2.0,
+        };
+        let resets = min(self.reset_count, i32::MAX as u64) as i32;
+        self.frequency(e) as f64 * per_reset.powi(resets)
+    }
+
+    /// Return the values of all <tt>table_len * 16</tt> counters, ordered by table word and then from
+    /// the least to the most significant nibble of each word, e.g. to export the dense counter
+    /// surface for offline analysis. An element's counters sit at its <tt>counter_positions</tt>.
+    pub fn decode_counters(&self) -> Vec<u8> {
+        self.decoded().collect()
+    }
+
+    /// Return the dense counter surface of <tt>table_len * 16</tt> nibble values, e.g. for
+    /// serialization to numpy or CSV. Equivalent to <tt>decode_counters</tt>.
+    ///
+    /// ```
+    /// use rust_frequency_counter::sketch::frequency_count_sketch::FrequencyCountSketch;
+    ///
+    /// let mut sketch = FrequencyCountSketch::new(512);
+    /// sketch.increment("key");
+    /// let counters = sketch.dense_counters();
+    /// assert_eq!(counters.len(), sketch.raw_table().len() * 16);
+    /// for position in sketch.counter_positions("key") {
+    ///     assert_eq!(counters[position], 1);
+    /// }
+    /// ```
+    pub fn dense_counters(&self) -> Vec<u8> {
+        self.decode_counters()
+    }
+
+    /// Iterates over the counter values in the order of <tt>decode_counters</tt>.
+    fn decoded(&self) -> impl Iterator<Item = u8> + '_ {
+        self.table
+            .iter()
+            .flat_map(|w| (0..16).map(move |n| ((w >> (n << 2)) & 0xf) as u8))
+    }
+
+    /// Reads the four counters of the element with the given spread hash code.
+    fn counters(&self, block_hash: usize) -> [u8; DEPTH] {
+        let mut count:[u8; DEPTH] = [0; DEPTH];
+        let (words, nibbles) = self.positions(block_hash);
+        self.prefetch(&words);
+        for (i, c) in count.iter_mut().enumerate() {
+            *c = ((self.table[words[i]] >> (nibbles[i] << 2)) & 0xf) as u8;
+        }
+        count
+    }
+
+    /// Hints the processor to load the cache lines holding the given words before they are accessed.
+    /// In the default layout the four words share one 64-byte block, so it is prefetched once. This
+    /// is a no-op unless the <tt>prefetch</tt> feature is enabled on x86_64 or aarch64.
+    #[inline(always)]
+    fn prefetch(&self, words: &[usize; DEPTH]) {
+        #[cfg(all(feature = "prefetch", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        {
+            let lines = if self.independent_rows || self.block_shift > 3 { 4 } else { 1 };
+            for &word in &words[..lines] {
+                prefetch_line(&self.table[word & !7]);
+            }
+        }
+        #[cfg(not(all(feature = "prefetch", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+        let _ = words;
+    }
+
+    /// Return the hash code the sketch places the element by: its <tt>default_hash_code</tt>, or the
+    /// keyed SipHash of a sketch created by <tt>new_with_seed</tt>. This is the hash code expected
+    /// by <tt>frequency_hashed</tt> and <tt>increment_hashed</tt>.
+    pub fn hash_code<E: Hash>(&self, e: E) -> u64 {
+        match self.hash_seed {
+            Some(seed) => {
+                let mut hasher = SipHasher13::new_with_keys(seed, 0);
+                e.hash(&mut hasher);
+                hasher.finish()
+            }
+            None => default_hash_code(e),
+        }
+    }
+
+    /// Return the hash code of a raw byte key, as <tt>bytes_hash_code</tt> or keyed by the seed.
+    fn hash_bytes(&self, bytes: &[u8]) -> u64 {
+        match self.hash_seed {
+            Some(seed) => {
+                let mut hasher = SipHasher13::new_with_keys(seed, 0);
+                hasher.write(bytes);
+                hasher.finish()
+            }
+            None => bytes_hash_code(bytes),
+        }
+    }
+
+    /// Return the four table word indices and the nibble index within each word of the counters
+    /// that <tt>increment</tt> and <tt>frequency</tt> use for the element, e.g. to build a sharding
+    /// layer consistent with the sketch.
+    pub fn locate<E: Hash>(&self, e: E) -> ([usize; DEPTH], [usize; DEPTH]) {
+        self.positions(spread(self.hash_code(e)))
+    }
+
+    /// Return the indices of the four table words that <tt>increment</tt> writes for the element,
+    /// the word half of <tt>locate</tt>, e.g. to lock exactly those words in a lock-striped
+    /// concurrent wrapper. The words lie in distinct quarters of one block, or of up to four blocks
+    /// for a sketch created by <tt>new_independent</tt>.
+    pub fn touched_words<E: Hash>(&self, e: E) -> [usize; DEPTH] {
+        self.locate(e).0
+    }
+
+    /// Return the indices of the element's four counters in the order of <tt>decode_counters</tt>.
+    pub fn counter_positions<E: Hash>(&self, e: E) -> [usize; DEPTH] {
+        flat_positions(self.positions(spread(self.hash_code(e))))
+    }
+
+    /// Estimates the fraction of distinct keys that would share at least one counter with another
+    /// key in a sketch created by <tt>new(maximum_size)</tt>, which helps choosing the maximum size
+    /// before deploying. The table is not allocated.
+    pub fn collision_estimate<E: Hash, I: IntoIterator<Item = E>>(maximum_size: usize, keys: I) -> f64 {
+        let (table_len, _, _) = dimensions_for(maximum_size);
+        let block_shift = DEFAULT_BLOCK_WORDS.trailing_zeros();
+        let block_mask = (table_len >> block_shift) - 1;
+        let hash_codes: HashSet<u64> = keys.into_iter().map(default_hash_code).collect();
+        if hash_codes.is_empty() {
+            return 0.0;
+        }
+        let positions: Vec<[usize; DEPTH]> = hash_codes
+            .iter()
+            .map(|&h| flat_positions(derive_positions(block_mask, block_shift, spread(h))))
+            .collect();
+        let mut occupancy: HashMap<usize, u32> = HashMap::new();
+        for p in positions.iter().flatten() {
+            *occupancy.entry(*p).or_insert(0) += 1;
+        }
+        let colliding = positions.iter().filter(|p| p.iter().any(|c| occupancy[c] > 1)).count();
+        colliding as f64 / positions.len() as f64
+    }
+
+    /// Return the fraction of the sampled keys whose four counters, as read by
+    /// <tt>counters_for</tt>, are not all equal. Unequal readings mean that other keys raised some
+    /// of the key's counters, so a high rate on the live sketch suggests a larger maximum size.
+    /// Unlike <tt>collision_estimate</tt>, this reflects the keys actually counted. Keys that were
+    /// never incremented but share counters with counted ones are also reported as colliding.
+    pub fn collision_rate<E: Hash>(&self, sample_keys: &[E]) -> f64 {
+        if sample_keys.is_empty() {
+            return 0.0;
+        }
+        let colliding = sample_keys
+            .iter()
+            .filter(|key| {
+                let count = self.counters_for(key);
+                count.iter().any(|&c| c != count[0])
+            })
+            .count();
+        colliding as f64 / sample_keys.len() as f64
+    }
+
+    /// Return whether the sketch looks too small for its workload, suggesting a larger maximum
+    /// size. It is considered undersized when more than half of its counters are non-zero, the
+    /// threshold at which an adaptive sketch considers the table crowded, or when the
+    /// <tt>collision_rate</tt> of the sampled keys exceeds 0.9. A sketch sized for its distinct keys
+    /// sees a collision rate around 0.6, as a key shares one of its four counters with another key
+    /// more often than not. This scans the whole table.
+    pub fn is_undersized<E: Hash>(&self, sample_keys: &[E]) -> bool {
+        let saturation = 1.0 - self.histogram()[0] as f64 / self.counter_count() as f64;
+        saturation > 0.5 || self.collision_rate(sample_keys) > 0.9
+    }
+
+    /// Derives the counter positions of the element with the given spread hash code.
+    fn positions(&self, block_hash: usize) -> ([usize; DEPTH], [usize; DEPTH]) {
+        if self.independent_rows {
+            derive_independent_positions(self.block_mask, self.block_shift, block_hash)
+        } else {
+            derive_positions(self.block_mask, self.block_shift, block_hash)
+        }
+    }
+
+    /// Return the number of increments that raised at least one counter since the last reset.
+    /// As every occurrence of a key counts until its counters saturate, this is an upper bound of the
+    /// distinct keys observed in the current sample window, and it is halved along with the counters
+    /// when the sketch ages.
+    pub fn estimated_cardinality(&self) -> usize {
+        self.size
+    }
+
+    /// Return how close the sketch is to its next count-driven reset, as the fraction of the sample
+    /// size counted since the last reset. The fraction lies between 0 and 1, and drops after every
+    /// reset as the sample accounting is halved along with the counters.
+    pub fn occupancy(&self) -> f64 {
+        self.size as f64 / self.sample_size as f64
+    }
+
+    /// Return whether the sketch has observed enough traffic for its estimates to be meaningful,
+    /// e.g. to admit every candidate into a cache until then. The sketch is warm once the increments
+    /// counted towards the sample since its creation reach the warmup threshold, by default the
+    /// sample size, so that it has filled one whole window. Unlike the sample accounting, this count
+    /// is not aged, and a sketch stays warm.
+    pub fn is_warm(&self) -> bool {
+        self.events >= self.warmup
+    }
+
+    /// Return an approximate count of the distinct keys incremented over the lifetime of the sketch,
+    /// e.g. to size it. Unlike <tt>estimated_cardinality</tt>, the count is kept by a separate
+    /// HyperLogLog estimator fed with the same hash codes, so it is not affected by saturation or
+    /// aging. Its standard error is about 6.5%.
+    #[cfg(feature = "distinct-count")]
+    pub fn estimated_distinct(&self) -> u64 {
+        self.distinct.estimate()
+    }
+
+    /// Estimates the number of distinct keys held by the sketch from the fraction of counters that
+    /// are still zero (linear counting). Every key sets four counters, so with <tt>m</tt> counters and
+    /// a zero fraction <tt>v</tt> the estimate is <tt>-(m / 4) * ln(v)</tt>. Keys whose counters were
+    /// aged back to zero are no longer counted. A table without any zero counter is saturated and
+    /// the estimate is computed as if a single counter were still zero.
+    pub fn linear_counting_estimate(&self) -> f64 {
+        let counters = self.counter_count();
+        let zeros = self.decoded().filter(|&c| c == 0).count();
+        let fraction = max(zeros, 1) as f64 / counters as f64;
+        -(counters as f64 / 4.0) * fraction.ln()
+    }
+
     /// Increments the popularity of the element if it does not exceed the maximum (15). The popularity
     /// of all elements will be periodically down sampled when the observed events exceed a threshold.
     /// This process provides a frequency aging to allow expired long term entries to fade away.
     pub fn increment<E: Hash>(&mut self, e: E) {
-        let mut index:[usize; 8] = [0; 8];
-        let hash_code = default_hash_code(e);
-        let block_hash = self.spread(hash_code);
-        let counter_hash = self.rehash(block_hash);
-        let block = (block_hash & self.block_mask) << 3;
-        for i in 0..4 {
-            let h = counter_hash >> (i << 3);
-            index[i] = (h >> 1) & 15;
-            let offset = h & 1;
-            index[i + 4] = block + offset + (i << 1u64);
-        }
-        let added = self.increment_at(index[4], index[0])
-            | self.increment_at(index[5], index[1])
-            | self.increment_at(index[6], index[2])
-            | self.increment_at(index[7], index[3]);
-
-        if added {
-            self.size += 1;
-            if self.size == self.sample_size {
+        self.increment_hash_code(self.hash_code(e));
+    }
+
+    /// Increments the popularity of the element like <tt>increment</tt>, first aging the sketch by
+    /// the time elapsed since the previous call as <tt>maybe_reset_after</tt> does with the period
+    /// set by <tt>set_reset_period</tt>, so that the counting path drives the wall-clock aging
+    /// without a separate timer. The first call only records <tt>now</tt>, and an instant earlier
+    /// than the previous one counts as no time elapsed.
+    #[cfg(feature = "std")]
+    pub fn increment_at_time<E: Hash>(&mut self, e: E, now: Instant) {
+        if let (Some(period), Some(last)) = (self.reset_period, self.last_instant) {
+            self.maybe_reset_after(now.saturating_duration_since(last), period);
+        }
+        self.last_instant = Some(self.last_instant.map_or(now, |last| max(last, now)));
+        self.increment(e);
+    }
+
+    /// Increments the popularity of the element with the given <tt>hash_code</tt>, so that a
+    /// key hashed once can be counted by several sketches.
+    pub fn increment_hashed(&mut self, hash_code: u64) {
+        self.increment_hash_code(hash_code);
+    }
+
+    /// Increments the popularity of a key that is already a well-distributed 64-bit fingerprint,
+    /// feeding it straight into the supplemental hashing like <tt>increment_hashed</tt>. This avoids
+    /// the cost of the default hasher, but keys that are not uniformly distributed, such as small
+    /// sequential ids, rely on <tt>spread</tt> alone to place them, and any seed is ignored.
+    pub fn increment_u64(&mut self, key: u64) {
+        self.increment_hashed(key);
+    }
+
+    /// Increments the popularity of the element whose hash code has already been passed through
+    /// <tt>spread</tt>, matching <tt>frequency_from_block_hash</tt>. The caller is responsible for
+    /// applying <tt>spread(hash_code(e))</tt>. Where usize is narrower than 64 bits the block hash
+    /// lacks the high bits of the spread hash, so <tt>estimated_distinct</tt> is unreliable for
+    /// elements counted this way.
+    pub fn increment_from_block_hash(&mut self, block_hash: usize) {
+        self.increment_spread_hash(block_hash as u64);
+    }
+
+    /// Increments the popularity of the element like <tt>increment</tt>, returning whether the
+    /// increment reached the sample size and aged the sketch with <tt>reset</tt>.
+    pub fn increment_checked<E: Hash>(&mut self, e: E) -> bool {
+        self.increment_hash_code(self.hash_code(e))
+    }
+
+    /// Increments the popularity of the element like <tt>increment</tt>, returning whether its hash
+    /// code has now been incremented <tt>SUSPICIOUS_HASH_RUN</tt> or more times in a row. Keys that
+    /// hash to a constant, such as <tt>()</tt> and other zero-sized values or a degenerate
+    /// <tt>Hash</tt> implementation, all map to the same four counters, so every such event
+    /// inflates a single key until it saturates. A long run of one hash code usually reveals such
+    /// a bug, and the caller can log it; a genuinely hot key repeating that often is also reported.
+    pub fn increment_checked_key<E: Hash>(&mut self, e: E) -> bool {
+        let hash_code = self.hash_code(e);
+        let (last, run) = self.hash_run;
+        let run = if last == hash_code { run.saturating_add(1) } else { 1 };
+        self.hash_run = (hash_code, run);
+        self.increment_hash_code(hash_code);
+        run >= SUSPICIOUS_HASH_RUN
+    }
+
+    /// Increments the popularity of the element like <tt>increment</tt>, but without checking the
+    /// counters for saturation, for workloads known to stay well below 15 between resets. Each
+    /// counter is incremented with a branch-free add that wraps within its nibble, so breaking the
+    /// promise is never undefined behavior and never disturbs neighbouring counters, but a saturated
+    /// counter silently wraps around to 0. Every call counts towards the sample size.
+    pub fn increment_unchecked<E: Hash>(&mut self, e: E) {
+        let spread_hash = spread64(self.hash_code(e));
+        #[cfg(feature = "distinct-count")]
+        self.distinct.observe(spread_hash);
+        self.advance_reset_sweep();
+        let block_hash = spread_hash as usize;
+        let (words, nibbles) = self.positions(block_hash);
+        for (&word, &nibble) in words.iter().zip(nibbles.iter()) {
+            let mask = 0xfu64 << (nibble << 2);
+            let w = self.table[word];
+            self.table[word] = (w & !mask) | (w.wrapping_add(1u64 << (nibble << 2)) & mask);
+        }
+        self.count_addition();
+    }
+
+    /// Records an access to the element and returns its resulting estimated frequency, hashing the
+    /// element only once. This is the canonical per-access entry point for a cache; if the access
+    /// triggered a <tt>reset</tt> the returned frequency reflects the aged counters.
+    pub fn access<E: Hash>(&mut self, e: E) -> u8 {
+        let hash_code = self.hash_code(e);
+        self.increment_hash_code(hash_code);
+        self.frequency_hashed(hash_code)
+    }
+
+    /// Records an access to the element like <tt>access</tt>, but returns its estimated frequency
+    /// from before the access, e.g. for a cache GET that decides on the prior popularity. The
+    /// element is hashed only once and the access may trigger a <tt>reset</tt> as usual.
+    pub fn touch<E: Hash>(&mut self, e: E) -> u8 {
+        let spread_hash = spread64(self.hash_code(e));
+        let before = self.frequency_from_block_hash(spread_hash as usize);
+        self.increment_spread_hash(spread_hash);
+        before
+    }
+
+    /// Records an access to the candidate and decides whether it should be admitted into the cache
+    /// in place of the victim, following TinyLfu: the candidate is admitted only when its estimated
+    /// frequency, including this access, is greater than the victim's.
+    pub fn admit<E: Hash>(&mut self, candidate: E, victim: E) -> bool {
+        let hash_code = self.hash_code(candidate);
+        self.increment_hash_code(hash_code);
+        self.frequency_hashed(hash_code) > self.frequency(victim)
+    }
+
+    /// Increments the popularity of the element <tt>count</tt> times, as if <tt>increment</tt> were
+    /// called repeatedly, including saturation and any resets reaching the sample size triggers.
+    /// The element is hashed only once.
+    pub fn increment_by<E: Hash>(&mut self, e: E, count: u32) {
+        let spread_hash = spread64(self.hash_code(e));
+        #[cfg(feature = "distinct-count")]
+        if count > 0 {
+            self.distinct.observe(spread_hash);
+        }
+        let block_hash = spread_hash as usize;
+        for _ in 0..count {
+            self.advance_reset_sweep();
+            if self.increment_counters(block_hash) {
+                self.count_addition();
+            } else if self.reset_sweep.is_none() {
+                // Saturated counters stay saturated until a reset, which requires an addition, but
+                // a pending sweep keeps advancing with every increment and may age them
+                break;
+            }
+        }
+    }
+
+    /// Increments the popularity of a raw byte key. Unlike <tt>increment(bytes)</tt>, the bytes are
+    /// fed to the hasher as-is, without the length prefix that <tt>Hash</tt> adds for slices. See
+    /// <tt>bytes_hash_code</tt>.
+    pub fn increment_bytes(&mut self, bytes: &[u8]) {
+        self.increment_hash_code(self.hash_bytes(bytes));
+    }
+
+    /// Increments the popularity of a key by its byte representation, like <tt>increment_bytes</tt>.
+    /// This avoids the <tt>Hash</tt> bound and its framing, so string and byte keys with the same
+    /// contents count as the same key.
+    pub fn increment_key(&mut self, key: impl AsRef<[u8]>) {
+        self.increment_bytes(key.as_ref());
+    }
+
+    /// Increments the popularity of every element of the iterator, e.g. to load a large offline
+    /// dataset, leaving the sketch exactly as a loop of <tt>increment</tt> calls would, including the
+    /// resets at the sample size boundaries. The elements are hashed in batches ahead of counting,
+    /// and with the <tt>prefetch</tt> feature the blocks of a batch are requested before any of them
+    /// is updated, so that the memory accesses of one batch overlap instead of stalling one by one.
+    /// Beyond that, every element goes through the same sample accounting as <tt>increment</tt> and
+    /// each reset sweeps the whole table as usual, so neither the bookkeeping nor the aging is
+    /// amortized over the batch.
+    pub fn increment_batch_with_aging<E: Hash, I: IntoIterator<Item = E>>(&mut self, items: I) {
+        let mut items = items.into_iter();
+        let mut batch = [0u64; BATCH_LEN];
+        loop {
+            let mut len = 0;
+            for (slot, e) in batch.iter_mut().zip(items.by_ref()) {
+                *slot = spread64(self.hash_code(e));
+                len += 1;
+            }
+            for &spread_hash in &batch[..len] {
+                self.prefetch(&self.positions(spread_hash as usize).0);
+            }
+            for &spread_hash in &batch[..len] {
+                self.increment_spread_hash(spread_hash);
+            }
+            if len < BATCH_LEN {
+                return;
+            }
+        }
+    }
+
+    /// Increments the popularity of every line read from <tt>reader</tt> as a string key, as
+    /// <tt>increment(line)</tt> would, e.g. to replay a production access log of one key per line
+    /// for benchmarking. Line endings are stripped as by <tt>BufRead::lines</tt>. Return the number
+    /// of lines counted, or the first read error, in which case the lines before it stay counted.
+    #[cfg(feature = "std")]
+    pub fn increment_from_reader<R: BufRead>(&mut self, reader: R) -> std::io::Result<usize> {
+        let mut count = 0;
+        for line in reader.lines() {
+            self.increment(line?.as_str());
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Increments the popularity of every element by its count, e.g. to warm up a cold sketch from
+    /// persisted historical counts.
+    pub fn load_histogram<E: Hash, I: IntoIterator<Item = (E, u32)>>(&mut self, entries: I) {
+        for (e, count) in entries {
+            self.increment_by(e, count);
+        }
+    }
+
+    /// Increments the counters of the element with the given hash code, returning whether the
+    /// sketch was reset.
+    fn increment_hash_code(&mut self, hash_code: u64) -> bool {
+        self.increment_spread_hash(spread64(hash_code))
+    }
+
+    /// Increments the counters of the element with the given 64-bit spread hash code, returning
+    /// whether the sketch was reset. The distinct counter observes all 64 bits, while the counters
+    /// are placed by the block hash it folds to.
+    fn increment_spread_hash(&mut self, spread_hash: u64) -> bool {
+        #[cfg(feature = "distinct-count")]
+        self.distinct.observe(spread_hash);
+        self.advance_reset_sweep();
+        self.increment_counters(spread_hash as usize) && self.count_addition()
+    }
+
+    /// Records an increment that changed at least one counter, aging the sketch when the sample
+    /// size is reached. The count never exceeds the sample size. Return whether the sketch was reset,
+    /// or an incremental reset was started.
+    fn count_addition(&mut self) -> bool {
+        self.size = counted_size(self.size, self.sample_size);
+        self.events += 1;
+        if self.aging_enabled && self.size >= self.sample_size && self.reset_sweep.is_none() {
+            if self.incremental_reset {
+                self.reset_sweep = Some(ResetSweep::default());
+            } else {
                 self.reset();
             }
+            return true;
         }
+        false
     }
 
-    /// Reduces every counter by half of its original value.
+    /// Ages the next chunk of the table if an incremental reset is in progress.
+    fn advance_reset_sweep(&mut self) {
+        if let Some(mut sweep) = self.reset_sweep.take() {
+            let end = min(sweep.cursor + RESET_CHUNK_WORDS, self.table_len);
+            self.age_words(end, &mut sweep);
+            if end == self.table_len {
+                self.finish_reset(sweep);
+            } else {
+                self.reset_sweep = Some(sweep);
+            }
+        }
+    }
+
+    /// Increments the four counters of the element with the given spread hash code, returning
+    /// whether any of them was below the maximum. The positions are destructured into locals so
+    /// that the hot path only indexes the table.
+    fn increment_counters(&mut self, block_hash: usize) -> bool {
+        let (words, nibbles) = self.positions(block_hash);
+        self.prefetch(&words);
+        let ([w0, w1, w2, w3], [n0, n1, n2, n3]) = (words, nibbles);
+        self.increment_at(w0, n0)
+            | self.increment_at(w1, n1)
+            | self.increment_at(w2, n2)
+            | self.increment_at(w3, n3)
+    }
+
+    /// Reduces every counter by half of its original value. If an incremental reset is in progress,
+    /// this completes it by aging the rest of the table.
     pub fn reset(&mut self) {
-        let mut count = 0u8;
-        for i in &mut self.table {
-            count += bit_count(*i & 0x1111111111111111);
-            *i = *i >> 1 & 0x7777777777777777;
+        let mut sweep = self.reset_sweep.take().unwrap_or_default();
+        self.age_words(self.table_len, &mut sweep);
+        self.finish_reset(sweep);
+    }
+
+    /// Resets the sketch like <tt>reset</tt> and returns the total counter mass, the sum of all
+    /// counter values, that the aging discarded, e.g. for decay-aware metrics. Halving removes about
+    /// half of the mass, and slightly more when aggressive aging or a decay factor applies. If an
+    /// incremental reset is in progress, only the mass removed by completing it is reported.
+    pub fn reset_and_report(&mut self) -> u64 {
+        let before = self.total_mass();
+        self.reset();
+        let after = self.total_mass();
+        debug_assert!(after <= before, "aging raised the mass from {} to {}", before, after);
+        before - after
+    }
+
+    /// Return the total counter mass, the sum of all counter values. This scans the whole table.
+    pub fn total_mass(&self) -> u64 {
+        self.table.iter().map(|&w| nibble_sum_word(w) as u64).sum()
+    }
+
+    /// Halves the table words from the sweep's cursor up to <tt>end</tt>, counting the odd counters
+    /// that lose a half and, for adaptive sketches, the non-zero ones.
+    fn age_words(&mut self, end: usize, sweep: &mut ResetSweep) {
+        let adaptive = self.adaptive_bounds.is_some();
+        let aggressive = self.aggressive_aging;
+        let decay_factor = self.decay_factor;
+        for i in &mut self.table[sweep.cursor..end] {
+            if adaptive {
+                sweep.nonzero += bit_count((*i | *i >> 1 | *i >> 2 | *i >> 3) & 0x1111111111111111) as usize;
+            }
+            if let Some(factor) = decay_factor {
+                sweep.odd += bit_count(*i & 0x1111111111111111) as usize;
+                let mut decayed = 0u64;
+                for n in 0..16 {
+                    decayed |= ((((*i >> (n << 2)) & 0xf) * factor) >> 8) << (n << 2);
+                }
+                *i = decayed;
+                continue;
+            }
+            let saturated = *i & (*i >> 1) & (*i >> 2) & (*i >> 3) & 0x1111111111111111;
+            sweep.odd += i.halve_nibbles();
+            if aggressive {
+                // Every saturated nibble now holds 7, so subtracting 4 cannot borrow
+                *i -= saturated << 2;
+            }
+        }
+        sweep.cursor = end;
+    }
+
+    /// Updates the sample accounting once the whole table has been aged.
+    fn finish_reset(&mut self, sweep: ResetSweep) {
+        self.size = match self.decay_factor {
+            Some(factor) => scale_size(self.size, factor, 256),
+            None => halved_size(self.size, sweep.odd),
+        };
+        if let Some((lower, upper)) = self.adaptive_bounds {
+            let saturation = sweep.nonzero as f64 / self.counter_count() as f64;
+            if saturation > 0.5 {
+                self.sample_size = max(self.sample_size >> 1, lower);
+            } else if saturation < 0.25 {
+                self.sample_size = min(self.sample_size.saturating_mul(2), upper);
+            }
+            self.size = min(self.size, self.sample_size);
+        }
+        self.record_aging();
+    }
+
+    /// Ages the sketch by wall-clock time rather than by event count. The elapsed time since the
+    /// previous call is accumulated, and <tt>reset</tt> runs once for every full period, carrying the
+    /// leftover time over to the next call. At most as many resets run per call as it takes to clear
+    /// every counter, four for halving and more for a gentler decay factor, so that a long pause
+    /// costs no more than that however many periods it spans. Return whether the sketch was reset.
+    ///
+    /// Panics if <tt>period</tt> is zero.
+    #[cfg(feature = "std")]
+    pub fn maybe_reset_after(&mut self, elapsed: Duration, period: Duration) -> bool {
+        assert!(!period.is_zero(), "reset period must be positive");
+        self.elapsed = self.elapsed.saturating_add(elapsed);
+        let (elapsed, period) = (self.elapsed.as_nanos(), period.as_nanos());
+        let leftover = elapsed % period;
+        self.elapsed = Duration::new((leftover / 1_000_000_000) as u64, (leftover % 1_000_000_000) as u32);
+        let resets = min(elapsed / period, self.resets_to_clear() as u128);
+        for _ in 0..resets {
+            self.reset();
+        }
+        resets > 0
+    }
+
+    /// Return the number of resets that take a saturated counter to 0.
+    #[cfg(feature = "std")]
+    fn resets_to_clear(&self) -> u32 {
+        match self.decay_factor {
+            Some(factor) => {
+                let mut counter = MAX_FREQUENCY as u64;
+                let mut resets = 0;
+                while counter > 0 {
+                    counter = (counter * factor) >> 8;
+                    resets += 1;
+                }
+                resets
+            }
+            None => 4,
+        }
+    }
+
+    /// Scales every counter by <tt>numerator / denominator</tt>, rounding down and saturating at the
+    /// maximum (15), and scales the sample accounting by the same factor. <tt>decay(1, 2)</tt> halves
+    /// the counters and the accounting exactly like the default <tt>reset</tt>, while e.g.
+    /// <tt>decay(3, 4)</tt> ages more gently. The factor is applied as given, regardless of the decay
+    /// factor or aggressive aging configured for <tt>reset</tt>.
+    ///
+    /// Panics if <tt>denominator</tt> is zero.
+    pub fn decay(&mut self, numerator: u8, denominator: u8) {
+        assert!(denominator > 0, "decay denominator must be positive");
+        // A pending sweep completes first, so that the odd counters counted below are the ones halved
+        if self.reset_sweep.is_some() {
+            self.reset();
+        }
+        let (numerator, denominator) = (numerator as u64, denominator as u64);
+        let halving = numerator * 2 == denominator;
+        let odd = if halving {
+            self.table.iter().map(|&w| bit_count(w & 0x1111111111111111) as usize).sum()
+        } else {
+            0
+        };
+        self.map_counters(|counter| min(counter * numerator / denominator, MAX_FREQUENCY as u64));
+        let size = if halving {
+            halved_size(self.size, odd)
+        } else {
+            scale_size(self.size, numerator, denominator)
+        };
+        self.size = min(size, self.sample_size - 1);
+        self.record_aging();
+    }
+
+    /// Ages the sketch like <tt>reset</tt>, except that counters above <tt>floor</tt> keep the floor
+    /// and lose only half of the excess, while counters at or below the floor are halved as usual.
+    /// This deviates from TinyLfu, whose uniform halving treats every key alike proportionally, so
+    /// that hot keys survive aging longer than cold ones. The sample accounting is scaled by the
+    /// fraction of counter mass that remains.
+    pub fn reset_weighted(&mut self, floor: u8) {
+        let floor = floor as u64;
+        let before = self.total_mass();
+        self.map_counters(|counter| {
+            if counter > floor {
+                floor + ((counter - floor) >> 1)
+            } else {
+                counter >> 1
+            }
+        });
+        let after = self.total_mass();
+        if before > 0 {
+            self.size = scale_size(self.size, after, before);
+        }
+        self.record_aging();
+    }
+
+    /// Scales every counter down by the factor bringing the total counter mass to at most
+    /// <tt>target_total_mass</tt>, e.g. to bound the overall weight of the history instead of
+    /// halving it blindly. The counters are scaled by <tt>target / total_mass</tt> and rounded
+    /// down, so the resulting mass never exceeds the target and counters keep their relative order,
+    /// although close ones may become equal. The sample accounting is scaled by the same factor.
+    /// Nothing changes if the mass is already within the target.
+    pub fn decay_to_mass(&mut self, target_total_mass: u64) {
+        let mass = self.total_mass();
+        if mass <= target_total_mass {
+            return;
+        }
+        self.map_counters(|counter| counter * target_total_mass / mass);
+        self.size = scale_size(self.size, target_total_mass, mass);
+        self.record_aging();
+    }
+
+    /// Adds the counters of a sketch that observed a disjoint stream to this one, saturating at 15,
+    /// along with its sample accounting. This is <tt>merge_weighted</tt> with equal weights, and
+    /// fails if the sketches differ in table length or block layout.
+    pub fn merge(&mut self, other: &FrequencyCountSketch) -> Result<(), SketchError> {
+        self.merge_weighted(other, 1, 1)
+    }
+
+    /// Folds a sketch of any dimensions into this one, e.g. a small per-shard sketch into a larger
+    /// global one, by replaying the other sketch's estimate of each of <tt>known_keys</tt> with
+    /// <tt>increment_by</tt>. Unlike <tt>merge</tt>, the tables need not match, but since a sketch
+    /// cannot enumerate its keys every key that is not listed is lost; the listed keys carry over
+    /// any overestimate from collisions in the other sketch. The replayed counts may trigger resets.
+    pub fn absorb<E: Hash>(&mut self, smaller: &FrequencyCountSketch, known_keys: &[E]) {
+        for key in known_keys {
+            self.increment_by(key, smaller.frequency(key) as u32);
+        }
+    }
+
+    /// Merges like <tt>merge</tt>, panicking if the sketches cannot be merged, e.g. to fail fast in
+    /// tests.
+    ///
+    /// # Panics
+    ///
+    /// Panics with both table lengths if the sketches differ in table length or block layout.
+    pub fn expect_merge(&mut self, other: &FrequencyCountSketch) {
+        if let Err(error) = self.merge(other) {
+            panic!(
+                "cannot merge a sketch of {} words into one of {} words: {}",
+                other.table_len, self.table_len, error
+            );
+        }
+    }
+
+    /// Combines the counters of another sketch into this one at unequal rates, e.g. to blend a
+    /// long-term sketch with a recent one. Each counter becomes
+    /// <tt>(self * self_weight + other * other_weight) / max(self_weight, other_weight)</tt>, rounded
+    /// half up and clamped to 15, so the heavier source keeps its counts while the lighter one is
+    /// scaled down by the ratio of the weights; equal weights sum the counters. The sample accounting
+    /// is combined the same way. Both sketches must share the table length and block layout. A
+    /// pending incremental reset of this sketch is completed first.
+    pub fn merge_weighted(
+        &mut self,
+        other: &FrequencyCountSketch,
+        self_weight: u8,
+        other_weight: u8,
+    ) -> Result<(), SketchError> {
+        self.check_compatible(other)?;
+        if self.reset_sweep.is_some() {
+            self.reset();
         }
-        self.size = (self.size - (count >> 2) as usize) >> 1;
+        let (a, b) = (self_weight as u64, other_weight as u64);
+        let scale = max(max(a, b), 1);
+        let combine = |x: u64, y: u64| (x * a + y * b + (scale >> 1)) / scale;
+        for (word, &theirs) in self.table.iter_mut().zip(other.table.iter()) {
+            let mut combined = 0u64;
+            for n in 0..16 {
+                let shift = n << 2;
+                let counter = combine((*word >> shift) & 0xf, (theirs >> shift) & 0xf);
+                combined |= min(counter, MAX_FREQUENCY as u64) << shift;
+            }
+            *word = combined;
+        }
+        // Combined in u128 so that restored accounting saturates at the sample size, not overflows
+        let (a, b, scale) = (a as u128, b as u128, scale as u128);
+        let size = (self.size as u128 * a + other.size as u128 * b + (scale >> 1)) / scale;
+        self.size = min(size, self.sample_size as u128 - 1) as usize;
+        Ok(())
+    }
+
+    /// Merges the counters of a sketch that observed an overlapping stream by keeping the larger of
+    /// each pair, so that occurrences seen by both sketches are not counted twice. Merging a sketch
+    /// with itself leaves it unchanged. The sample accounting keeps the larger size. Both sketches
+    /// must have the same dimensions and layout, and a pending incremental reset is completed first.
+    pub fn merge_max(&mut self, other: &FrequencyCountSketch) -> Result<(), SketchError> {
+        self.check_compatible(other)?;
+        if self.reset_sweep.is_some() {
+            self.reset();
+        }
+        for (word, &theirs) in self.table.iter_mut().zip(other.table.iter()) {
+            let mut merged = 0u64;
+            for n in 0..16 {
+                let mask = 0xfu64 << (n << 2);
+                merged |= max(*word & mask, theirs & mask);
+            }
+            *word = merged;
+        }
+        self.size = min(max(self.size, other.size), self.sample_size - 1);
+        Ok(())
+    }
+
+    /// Checks that the other sketch maps every key to the same counters, as required to combine or
+    /// compare the two tables counter by counter.
+    fn check_compatible(&self, other: &FrequencyCountSketch) -> Result<(), SketchError> {
+        if self.table_len != other.table_len {
+            return Err(SketchError::TableLengthMismatch { expected: self.table_len, actual: other.table_len });
+        }
+        if self.block_shift != other.block_shift || self.independent_rows != other.independent_rows {
+            return Err(SketchError::LayoutMismatch);
+        }
+        Ok(())
+    }
+
+    /// Return the cosine similarity of the two counter tables, treating every nibble as a vector
+    /// component, from 0.0 for disjoint popularity profiles to 1.0 for proportional ones. Two empty
+    /// sketches are considered identical. Both sketches must have the same dimensions and layout so
+    /// that a key maps to the same counters in each; a pending incremental reset is not completed.
+    pub fn similarity(&self, other: &FrequencyCountSketch) -> Result<f64, SketchError> {
+        self.check_compatible(other)?;
+        let (mut dot, mut ours_norm, mut theirs_norm) = (0u64, 0u64, 0u64);
+        for (&ours, &theirs) in self.table.iter().zip(other.table.iter()) {
+            for n in 0..16 {
+                let shift = n << 2;
+                let (x, y) = ((ours >> shift) & 0xf, (theirs >> shift) & 0xf);
+                dot += x * y;
+                ours_norm += x * x;
+                theirs_norm += y * y;
+            }
+        }
+        if ours_norm == 0 || theirs_norm == 0 {
+            return Ok(if ours_norm == theirs_norm { 1.0 } else { 0.0 });
+        }
+        Ok(dot as f64 / ((ours_norm as f64).sqrt() * (theirs_norm as f64).sqrt()))
+    }
+
+    /// Return every counter whose value differs between the two sketches as
+    /// <tt>(counter_index, self_value, other_value)</tt>, ordered by index, e.g. to locate where
+    /// replicas diverged. Counter indices follow <tt>decode_counters</tt>. Both sketches must have
+    /// the same dimensions and layout; the sample accounting is not compared.
+    pub fn diff(&self, other: &FrequencyCountSketch) -> Result<Vec<(usize, u8, u8)>, SketchError> {
+        self.check_compatible(other)?;
+        Ok(self
+            .decoded()
+            .zip(other.decoded())
+            .enumerate()
+            .filter(|(_, (ours, theirs))| ours != theirs)
+            .map(|(i, (ours, theirs))| (i, ours, theirs))
+            .collect())
+    }
+
+    /// Replaces every counter with the result of <tt>f</tt>, which must not exceed 15. A pending
+    /// incremental reset is completed first so that no word is aged twice as a result.
+    fn map_counters<F: Fn(u64) -> u64>(&mut self, f: F) {
+        if self.reset_sweep.is_some() {
+            self.reset();
+        }
+        for word in self.table.iter_mut() {
+            let mut mapped = 0u64;
+            for n in 0..16 {
+                mapped |= f((*word >> (n << 2)) & 0xf) << (n << 2);
+            }
+            *word = mapped;
+        }
+    }
+
+    /// Counts a completed aging of the whole table, a reset or one of its variants, and invokes the
+    /// reset callback.
+    fn record_aging(&mut self) {
+        self.reset_count = self.reset_count.saturating_add(1);
+        self.notify_reset();
+    }
+
+    /// Invokes the reset callback, if one is registered.
+    fn notify_reset(&self) {
+        if let Some(hook) = &self.reset_hook {
+            // A callback that panicked earlier is still invoked, as its state is its own concern
+            let mut callback = hook.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            (*callback)(self);
+        }
+    }
+
+    /// Checks the structural invariants of the sketch, returning the first one that is violated.
+    /// Intended as an oracle for fuzzing and for detecting corrupted snapshots.
+    pub fn check_invariants(&self) -> Result<(), SketchError> {
+        if !self.table_len.is_power_of_two() || self.table_len < MIN_TABLE_LEN {
+            return Err(SketchError::InvalidTableLength(self.table_len));
+        }
+        if self.table.len() != self.table_len {
+            return Err(SketchError::CorruptState(format!(
+                "table has {} words, expected {}",
+                self.table.len(),
+                self.table_len
+            )));
+        }
+        if self.table_len >> self.block_shift == 0 || self.block_mask != (self.table_len >> self.block_shift) - 1 {
+            return Err(SketchError::CorruptState(format!(
+                "block_mask {} does not match table_len {}",
+                self.block_mask, self.table_len
+            )));
+        }
+        if self.size > self.sample_size {
+            return Err(SketchError::CorruptState(format!(
+                "size {} exceeds sample_size {}",
+                self.size, self.sample_size
+            )));
+        }
+        // Counters are packed as nibbles, so this only fails once wider counters are introduced
+        if let Some((index, value)) = self.decoded().enumerate().find(|&(_, value)| value > MAX_FREQUENCY) {
+            return Err(SketchError::CounterOutOfRange { index, value });
+        }
+        Ok(())
+    }
+
+    /// Checks the structural invariants of the sketch, returning a description of the first one that
+    /// is violated. Intended as an oracle for property and fuzz tests.
+    #[cfg(feature = "debug-invariants")]
+    pub fn validate(&self) -> Result<(), String> {
+        self.check_invariants().map_err(|e| e.to_string())
     }
 
     /// Increments the specified counter by 1 if it is not already at the maximum value (15).
     fn increment_at(&mut self, i: usize, j: usize) -> bool {
-        let offset = (j as u64) << 2u64;
-        let mask = 0xfu64 << offset;
-        if (self.table[i] & mask) != mask {
-            self.table[i] += 1u64 << offset;
-            return true;
+        self.table[i].increment_nibble(j)
+    }
+}
+
+/// Derives the four table word indices and nibble indices of the counters of the element with the
+/// given spread hash code, in blocks of <tt>1 << block_shift</tt> words. The block is selected by
+/// the spread hash and each counter comes from a distinct quarter of the block, a 16-byte segment
+/// by default.
+pub(crate) fn derive_positions(
+    block_mask: usize,
+    block_shift: u32,
+    block_hash: usize,
+) -> ([usize; DEPTH], [usize; DEPTH]) {
+    let mut words:[usize; DEPTH] = [0; DEPTH];
+    let mut nibbles:[usize; DEPTH] = [0; DEPTH];
+    let row_shift = block_shift - 2;
+    let counter_hash = rehash(block_hash);
+    let block = (block_hash & block_mask) << block_shift;
+    for i in 0..DEPTH {
+        let h = counter_hash >> (i << 3);
+        nibbles[i] = (h >> row_shift) & 15;
+        let offset = h & ((1 << row_shift) - 1);
+        words[i] = block + offset + (i << row_shift);
+    }
+    (words, nibbles)
+}
+
+/// Derives the positions of the counters of the element with the given spread hash code like
+/// <tt>derive_positions</tt>, except that each depth rehashes the spread hash code with its own seed
+/// to select its block and counter.
+fn derive_independent_positions(
+    block_mask: usize,
+    block_shift: u32,
+    block_hash: usize,
+) -> ([usize; DEPTH], [usize; DEPTH]) {
+    let mut words:[usize; DEPTH] = [0; DEPTH];
+    let mut nibbles:[usize; DEPTH] = [0; DEPTH];
+    let row_shift = block_shift - 2;
+    for i in 0..DEPTH {
+        let row_hash = default_hash_code((i, block_hash));
+        let block = (row_hash as usize & block_mask) << block_shift;
+        let h = (row_hash >> 48) as usize;
+        nibbles[i] = (h >> row_shift) & 15;
+        words[i] = block + (h & ((1 << row_shift) - 1)) + (i << row_shift);
+    }
+    (words, nibbles)
+}
+
+/// Prefetches the cache line holding the word into all cache levels.
+#[cfg(all(feature = "prefetch", target_arch = "x86_64"))]
+#[inline(always)]
+fn prefetch_line(word: &u64) {
+    use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+    // SAFETY: SSE is part of the x86_64 baseline and a prefetch never faults
+    unsafe { _mm_prefetch(word as *const u64 as *const i8, _MM_HINT_T0) }
+}
+
+/// Prefetches the cache line holding the word into the L1 cache for reading.
+#[cfg(all(feature = "prefetch", target_arch = "aarch64"))]
+#[inline(always)]
+fn prefetch_line(word: &u64) {
+    // SAFETY: a prefetch only hints the memory system and never faults or writes memory
+    unsafe {
+        std::arch::asm!(
+            "prfm pldl1keep, [{0}]",
+            in(reg) word as *const u64,
+            options(nostack, readonly, preserves_flags)
+        )
+    }
+}
+
+/// Extracts the four counters at the given positions in parallel and return their minimum, shifting
+/// each word by its nibble offset in one vector register and reducing the lanes pairwise.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn min_counter_avx2(table: &[u64], words: &[usize; 4], nibbles: &[usize; 4]) -> u8 {
+    use std::arch::x86_64::*;
+    let lane = |i: usize| table[words[i]] as i64;
+    let shift = |i: usize| (nibbles[i] << 2) as i64;
+    let values = _mm256_set_epi64x(lane(3), lane(2), lane(1), lane(0));
+    let shifts = _mm256_set_epi64x(shift(3), shift(2), shift(1), shift(0));
+    let counters = _mm256_and_si256(_mm256_srlv_epi64(values, shifts), _mm256_set1_epi64x(0xf));
+    // Counters fit in the low 32 bits of each lane, so unsigned 32-bit minimums reduce them
+    let halves = _mm256_min_epu32(counters, _mm256_permute4x64_epi64::<0b01_00_11_10>(counters));
+    let lanes = _mm256_min_epu32(halves, _mm256_shuffle_epi32::<0b01_00_11_10>(halves));
+    _mm256_cvtsi256_si32(lanes) as u8
+}
+
+/// Converts word and nibble indices into counter indices in the order of <tt>decode_counters</tt>.
+fn flat_positions((words, nibbles): ([usize; DEPTH], [usize; DEPTH])) -> [usize; DEPTH] {
+    std::array::from_fn(|i| words[i] * 16 + nibbles[i])
+}
+
+/// Returns the table length, clamped maximum size and sample size of a sketch for the given maximum
+/// size of the cache.
+pub(crate) fn dimensions_for(maximum_size: usize) -> (usize, usize, usize) {
+    // 最大值，i32 / 2
+    let maximum = min(maximum_size, i32::MAX as usize >> 1);
+    let table_len:usize = max(ceiling_power_of_two(maximum as i32), 8) as usize;
+    (table_len, maximum, sample_size_for(maximum))
+}
+
+/// Returns the expected fraction of <tt>distinct</tt> keys sharing at least one counter with another
+/// key in a table of the given length with the default block layout. Another key lands in the same
+/// block with probability <tt>1 / blocks</tt>, and then shares a key's counter in one of the four
+/// rows of 32 counters with probability <tt>1 - (31/32)^4</tt>.
+fn expected_collision_rate(table_len: usize, distinct: usize) -> f64 {
+    let row_counters = (DEFAULT_BLOCK_WORDS * 16 / DEPTH) as f64;
+    let per_block = 1.0 - (1.0 - 1.0 / row_counters).powi(DEPTH as i32);
+    let shared = per_block / (table_len / DEFAULT_BLOCK_WORDS) as f64;
+    let others = distinct.saturating_sub(1) as f64;
+    -(others * (-shared).ln_1p()).exp_m1()
+}
+
+/// Returns the sample size for a clamped maximum size, ten times the maximum but at least 10. The
+/// product saturates rather than wrapping on 32-bit targets, where ten times the largest clamped
+/// maximum does not fit in a usize.
+pub(crate) const fn sample_size_for(maximum: usize) -> usize {
+    if maximum == 0 {
+        return 10;
+    }
+    maximum.saturating_mul(10)
+}
+
+/// Returns the range an adaptive sketch may tune its sample size in, given the default sample size.
+fn adaptive_bounds(sample_size: usize) -> (usize, usize) {
+    (max(sample_size >> 2, 1), sample_size.saturating_mul(4))
+}
+
+/// Merges a table serialized by <tt>to_bytes</tt> into a sketch as its chunks arrive, e.g. for
+/// streaming replication, with the same result as merging the deserialized sketch with
+/// <tt>merge</tt>. Every complete word is added to the target's counters as soon as it is
+/// received, so at most 7 bytes are buffered. The encoding has no header, so its
+/// framing is the byte length of the target's table, fixed once when the merger is created: bytes
+/// beyond it are rejected by <tt>feed</tt> and a short payload by <tt>finish</tt>. A rejected
+/// payload may already have been partially merged.
+#[derive(Debug)]
+pub struct SketchMerger<'a> {
+    target: &'a mut FrequencyCountSketch,
+    // Bytes of the partially received word
+    pending: [u8; 8],
+    pending_len: usize,
+    // Number of words merged so far, and their total counter mass
+    words: usize,
+    mass: usize,
+}
+
+impl<'a> SketchMerger<'a> {
+    /// Starts merging a serialized table into the target, completing its pending incremental reset.
+    pub fn new(target: &'a mut FrequencyCountSketch) -> Self {
+        if target.reset_sweep.is_some() {
+            target.reset();
         }
-        false
+        Self { target, pending: [0; 8], pending_len: 0, words: 0, mass: 0 }
     }
 
-    /// Applies a supplemental hash functions to defends against poor quality hash.
-    fn spread(&self, hash_code: u64) -> usize {
-        let mut x: u128 = hash_code as u128;
-        x ^= x >> 17;
-        x *= 0xed5ad4bb;
-        x ^= x >> 11;
-        x *= 0xac4c1b51;
-        x ^= x >> 15;
-        return x as usize;
+    /// Merges the complete words of the chunk and buffers the remainder for the next chunk. Chunks
+    /// may have any length, but together they must not exceed the target's table.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), SketchError> {
+        let expected = self.target.table_len * size_of::<u64>();
+        let received = self.words * size_of::<u64>() + self.pending_len + chunk.len();
+        if received > expected {
+            return Err(SketchError::InvalidByteLength { expected, actual: received });
+        }
+        for &byte in chunk {
+            self.pending[self.pending_len] = byte;
+            self.pending_len += 1;
+            if self.pending_len == self.pending.len() {
+                self.merge_word(u64::from_le_bytes(self.pending));
+                self.pending_len = 0;
+            }
+        }
+        Ok(())
     }
 
-    /// Applies another round of hashing for additional randomization.
-    fn rehash(&self, x: usize) -> usize {
-        let mut x = x as u128;
-        x *= 0x31848bab;
-        x ^= x >> 14;
-        return x as usize;
+    /// Completes the merge, combining the sample accounting estimated from the merged counters
+    /// like <tt>from_bytes</tt>. Fails if fewer bytes than the target's table were fed.
+    pub fn finish(self) -> Result<(), SketchError> {
+        let expected = self.target.table_len * size_of::<u64>();
+        let received = self.words * size_of::<u64>() + self.pending_len;
+        if received != expected {
+            return Err(SketchError::InvalidByteLength { expected, actual: received });
+        }
+        let sketch = self.target;
+        sketch.size = min(sketch.size.saturating_add(self.mass.div_ceil(4)), sketch.sample_size - 1);
+        Ok(())
     }
+
+    /// Adds the counters of the next word of the payload to the target's, saturating at 15.
+    fn merge_word(&mut self, theirs: u64) {
+        let word = &mut self.target.table[self.words];
+        let mut merged = 0u64;
+        for n in 0..16 {
+            let shift = n << 2;
+            let count = (theirs >> shift) & 0xf;
+            self.mass += count as usize;
+            merged |= min(((*word >> shift) & 0xf) + count, MAX_FREQUENCY as u64) << shift;
+        }
+        *word = merged;
+        self.words += 1;
+    }
+}
+
+/// Two sketches are equal when they have the same dimensions, sample accounting and counters.
+/// Behavioral settings such as the aging mode are not compared.
+impl PartialEq for FrequencyCountSketch {
+    fn eq(&self, other: &Self) -> bool {
+        self.table_len == other.table_len
+            && self.block_mask == other.block_mask
+            && self.sample_size == other.sample_size
+            && self.size == other.size
+            && self.max_size == other.max_size
+            && self.independent_rows == other.independent_rows
+            && self.hash_seed == other.hash_seed
+            && self.table == other.table
+    }
+}
+
+/// Applies a supplemental hash functions to defends against poor quality hash.
+pub fn spread(hash_code: u64) -> usize {
+    // The truncation to usize is the intended folding
+    spread64(hash_code) as usize
+}
+
+/// Applies the supplemental hashing of <tt>spread</tt>, keeping all 64 bits of the result even on
+/// targets where usize is narrower, for consumers such as the distinct counter that read the top bits.
+pub(crate) fn spread64(hash_code: u64) -> u64 {
+    // Each product multiplies fewer than 96 bits by a 32-bit constant, so the u128 arithmetic never
+    // overflows
+    let mut x: u128 = hash_code as u128;
+    x ^= x >> 17;
+    x *= 0xed5ad4bb;
+    x ^= x >> 11;
+    x *= 0xac4c1b51;
+    x ^= x >> 15;
+    x as u64
+}
+
+/// Applies another round of hashing for additional randomization.
+pub(crate) fn rehash(x: usize) -> usize {
+    // The 64-bit input times a 32-bit constant fits in u128, as in spread
+    let mut x = x as u128;
+    x *= 0x31848bab;
+    x ^= x >> 14;
+    x as usize
 }
 
 /// Returns the number of one-bits in the two's complement binary representation of the specified long value.
@@ -145,28 +2181,140 @@ pub fn bit_count(mut i: u64) -> u8 {
     i = i + (i >> 8);
     i = i + (i >> 16);
     i = i + (i >> 32);
-    (i & 0x7) as u8
+    (i & 0x7f) as u8
+}
+
+/// Returns <tt>size * numerator / denominator</tt> rounded down and saturated at <tt>usize::MAX</tt>,
+/// computed in u128 so that the product cannot overflow whatever the sample accounting holds.
+fn scale_size(size: usize, numerator: u64, denominator: u64) -> usize {
+    min(size as u128 * numerator as u128 / denominator as u128, usize::MAX as u128) as usize
 }
 
-///
+/// A table word packing fixed-width counters. The sketch variants share its implementations, so that
+/// they all increment and age their counters exactly like <tt>FrequencyCountSketch</tt>.
+pub(crate) trait CounterWord {
+    /// Increments the counter at the bit offset by 1 if it is below <tt>max</tt>, the largest value
+    /// of its width, returning whether it was raised.
+    fn increment_counter(&mut self, offset: usize, max: Self) -> bool;
+
+    /// Halves every counter, given the mask of their lowest bits and the mask of the bits a counter
+    /// keeps after the shift, returning the number of odd counters that lost a half.
+    fn halve_counters(&mut self, low_bits: Self, kept_bits: Self) -> usize;
+
+    /// Increments the 4-bit counter at the nibble index by 1 if it is not already at the maximum value
+    /// (15), returning whether it was raised.
+    fn increment_nibble(&mut self, nibble: usize) -> bool;
+
+    /// Halves every 4-bit counter, returning the number of odd counters that lost a half.
+    fn halve_nibbles(&mut self) -> usize;
+}
+
+macro_rules! counter_word {
+    ($word:ty) => {
+        impl CounterWord for $word {
+            fn increment_counter(&mut self, offset: usize, max: Self) -> bool {
+                let mask = max << offset;
+                if (*self & mask) != mask {
+                    *self += 1 << offset;
+                    return true;
+                }
+                false
+            }
+
+            fn halve_counters(&mut self, low_bits: Self, kept_bits: Self) -> usize {
+                let odd = (*self & low_bits).count_ones() as usize;
+                *self = *self >> 1 & kept_bits;
+                odd
+            }
+
+            fn increment_nibble(&mut self, nibble: usize) -> bool {
+                self.increment_counter(nibble << 2, 0xf)
+            }
+
+            fn halve_nibbles(&mut self) -> usize {
+                self.halve_counters(0x1111111111111111u64 as $word, 0x7777777777777777u64 as $word)
+            }
+        }
+    };
+}
+
+counter_word!(u32);
+counter_word!(u64);
+
+/// Halves every 4-bit counter of the table like <tt>FrequencyCountSketch::reset</tt> and returns the
+/// sample size that remains of <tt>size</tt>.
+pub(crate) fn halve_table<W: CounterWord>(table: &mut [W], size: usize) -> usize {
+    let odd = table.iter_mut().map(|word| word.halve_nibbles()).sum();
+    halved_size(size, odd)
+}
+
+/// Returns the sample size after an increment that raised at least one counter, saturated at the
+/// sample size so that the accounting stays within the sample while aging is deferred.
+pub(crate) fn counted_size(size: usize, sample_size: usize) -> usize {
+    min(size.saturating_add(1), sample_size)
+}
+
+/// Returns the sample size after halving a table whose counters included <tt>odd</tt> odd ones. The
+/// halves they lost are discounted, one per increment of the four counters of an element, so that
+/// the accounting tracks the remaining counter mass.
+pub(crate) fn halved_size(size: usize, odd: usize) -> usize {
+    size.saturating_sub(odd >> 2) >> 1
+}
+
+/// Returns the sum of the 16 nibble counters of the word, at most 240. The nibbles are first added
+/// in pairs within each byte, and the eight byte sums, at most 30 each, are then gathered into the
+/// top byte by a multiplication that cannot carry between bytes. Callers summing many words must
+/// accumulate in a wider integer.
+pub(crate) fn nibble_sum_word(word: u64) -> u32 {
+    let pairs = (word & 0x0f0f0f0f0f0f0f0f) + ((word >> 4) & 0x0f0f0f0f0f0f0f0f);
+    (pairs.wrapping_mul(0x0101010101010101) >> 56) as u32
+}
+
+/// Returns the count-mean-min estimate from an element's counters, given the total mass and the
+/// number of counters of the row each one was read from. Each counter is reduced by the row's mass
+/// excluding the counter, spread evenly over the row's other counters, and the median of the
+/// corrected readings is returned, averaging the two middle ones for an even count and never
+/// exceeding the smallest counter.
+pub(crate) fn count_mean_min<const N: usize>(count: [u8; N], row_mass: [u64; N], row_width: [usize; N]) -> f64 {
+    let mut corrected: [f64; N] = std::array::from_fn(|i| {
+        let c = count[i] as f64;
+        (c - (row_mass[i] as f64 - c) / (row_width[i] as f64 - 1.0)).max(0.0)
+    });
+    corrected.sort_unstable_by(f64::total_cmp);
+    let lowest = count.iter().copied().fold(MAX_FREQUENCY, min);
+    ((corrected[(N - 1) >> 1] + corrected[N >> 1]) / 2.0).min(lowest as f64)
+}
+
+/// Returns the hash code of the element computed with the standard library's default hasher.
 pub fn default_hash_code<E: Hash>(e: E) -> u64 {
     let mut hasher = DefaultHasher::new();
     e.hash(&mut hasher);
     hasher.finish()
 }
 
+/// Returns the hash code of the raw bytes computed with the standard library's default hasher,
+/// without the length prefix added when hashing a slice through <tt>Hash</tt>. The default hasher is
+/// currently SipHash-1-3 with zero keys, which sketches in other languages can reproduce, although
+/// the standard library does not guarantee this algorithm across releases.
+pub fn bytes_hash_code(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
 /// Returns the smallest power of two greater than or equal to num.
 pub fn ceiling_power_of_two(num: i32) -> u32 {
-    let a = number_of_leading_zeros(num - 1);
+    // i32::MIN wraps to i32::MAX and is clamped like any other large value
+    let a = number_of_leading_zeros(num.wrapping_sub(1));
     if a == 32 || a == 0 {
         return 1;
     }
     let n = (-1i32 as u32) >> a;
-    return if n >= (1 << 30) {
+    if n >= (1 << 30) {
         1 << 30
     } else {
         n + 1
-    };
+    }
 }
 
 /// Returns the number of zero bits preceding the highest-order ("leftmost") one-bit in the two's
@@ -184,7 +2332,7 @@ pub fn number_of_leading_zeros(i: i32) -> u8 {
     let mut b = i as u32;
     if b >= (1 << 16) {
         n -= 16;
-        b = b >> 16;
+        b >>= 16;
     }
     if b >= (1 << 8) {
         n -= 8;
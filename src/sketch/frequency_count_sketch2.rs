@@ -0,0 +1,110 @@
+use std::cmp::{max, min};
+use std::hash::Hash;
+use std::mem::size_of;
+
+use crate::sketch::frequency_count_sketch::{
+    ceiling_power_of_two, counted_size, default_hash_code, halved_size, rehash, sample_size_for, spread,
+    CounterWord,
+};
+
+/// A 2-bit variant of <tt>FrequencyCountSketch</tt> for workloads that only need to distinguish
+/// "never / once / twice / many". Each table word packs 32 counters that saturate at 3, so the
+/// table is half the length of the 4-bit sketch for the same maximum size while keeping the
+/// same block layout: an element's four counters live in a 64-byte block, each one selected from
+/// a distinct 16-byte segment.
+#[derive(Debug, Clone)]
+pub struct FrequencyCountSketch2 {
+    // Frequency reduction threshold
+    sample_size: usize,
+    block_mask: usize,
+    // Access frequency container
+    table: Vec<u64>,
+    table_len: usize,
+    size: usize,
+    max_size: usize,
+}
+
+impl FrequencyCountSketch2 {
+
+    /// Initializes a sketch that can estimate the popularity of elements given the maximum size of
+    /// the cache, using half the table length of the equivalent 4-bit sketch.
+    pub fn new(maximum_size: usize) -> Self {
+        let maximum = min(maximum_size, i32::MAX as usize >> 1);
+        let table_len:usize = max(ceiling_power_of_two(maximum as i32) >> 1, 8) as usize;
+        Self {
+            sample_size: sample_size_for(maximum),
+            block_mask: (table_len >> 3) - 1,
+            table: vec![0; table_len],
+            table_len,
+            size: 0,
+            max_size: maximum,
+        }
+    }
+
+    /// Return max size of this sketch
+    pub fn get_max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return table len of this sketch
+    pub fn get_table_len(&self) -> usize {
+        self.table_len
+    }
+
+    /// Return the number of bytes used by the counter table.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.table_len * size_of::<u64>()
+    }
+
+    /// Return the estimated number of occurrences of an element, up to the maximum (3).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        let mut count:[u8; 4] = [0; 4];
+        let (block, counter_hash) = self.block_of(default_hash_code(e));
+        for (i, c) in count.iter_mut().enumerate() {
+            let h = counter_hash >> (i << 3);
+            let index = (h >> 1) & 31;
+            let offset = h & 1;
+            *c = ((self.table[block + offset + (i << 1)] >> (index << 1)) & 0x3) as u8;
+        }
+        min(min(count[0], count[1]), min(count[2], count[3]))
+    }
+
+    /// Increments the popularity of the element if it does not exceed the maximum (3). The popularity
+    /// of all elements will be periodically down sampled when the observed events exceed a threshold.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        let (block, counter_hash) = self.block_of(default_hash_code(e));
+        let mut added = false;
+        for i in 0..4 {
+            let h = counter_hash >> (i << 3);
+            let index = (h >> 1) & 31;
+            let offset = h & 1;
+            added |= self.increment_at(block + offset + (i << 1), index);
+        }
+
+        if added {
+            self.size = counted_size(self.size, self.sample_size);
+            if self.size >= self.sample_size {
+                self.reset();
+            }
+        }
+    }
+
+    /// Reduces every counter by half of its original value.
+    pub fn reset(&mut self) {
+        // Each 2-bit counter keeps its high bit, shifted into the low one
+        let odd = self.table.iter_mut().map(|w| w.halve_counters(0x5555555555555555, 0x5555555555555555)).sum();
+        self.size = halved_size(self.size, odd);
+    }
+
+    /// Returns the start of the element's block and the hash used to select its counters.
+    fn block_of(&self, hash_code: u64) -> (usize, usize) {
+        let block_hash = spread(hash_code);
+        let counter_hash = rehash(block_hash);
+        ((block_hash & self.block_mask) << 3, counter_hash)
+    }
+
+    /// Increments the specified counter by 1 if it is not already at the maximum value (3).
+    fn increment_at(&mut self, i: usize, j: usize) -> bool {
+        self.table[i].increment_counter(j << 1, 0x3)
+    }
+}
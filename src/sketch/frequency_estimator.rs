@@ -0,0 +1,124 @@
+use std::hash::Hash;
+
+use crate::sketch::concurrent_frequency_sketch::ConcurrentFrequencySketch;
+use crate::sketch::depth_frequency_sketch::DepthFrequencySketch;
+use crate::sketch::frequency_count_sketch::FrequencyCountSketch;
+use crate::sketch::frequency_count_sketch2::FrequencyCountSketch2;
+use crate::sketch::generational_frequency_sketch::GenerationalFrequencySketch;
+use crate::sketch::hybrid_frequency_sketch::HybridFrequencySketch;
+use crate::sketch::recency_frequency_sketch::RecencyFrequencySketch;
+use crate::sketch::sliding_frequency_sketch::SlidingFrequencySketch;
+use crate::sketch::static_frequency_sketch::StaticFrequencySketch;
+#[cfg(feature = "word32")]
+use crate::sketch::word32_frequency_sketch::Word32FrequencySketch;
+
+/// A popularity estimator for a stream of cache access events, letting caches be generic over the
+/// sketch implementation or use a mock estimator in tests.
+pub trait FrequencyEstimator {
+    /// Records an occurrence of the element.
+    fn increment<E: Hash>(&mut self, e: E);
+
+    /// Return the estimated number of occurrences of the element.
+    fn frequency<E: Hash>(&self, e: E) -> u8;
+}
+
+impl FrequencyEstimator for FrequencyCountSketch {
+    fn increment<E: Hash>(&mut self, e: E) {
+        FrequencyCountSketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        FrequencyCountSketch::frequency(self, e)
+    }
+}
+
+impl FrequencyEstimator for FrequencyCountSketch2 {
+    fn increment<E: Hash>(&mut self, e: E) {
+        FrequencyCountSketch2::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        FrequencyCountSketch2::frequency(self, e)
+    }
+}
+
+impl FrequencyEstimator for SlidingFrequencySketch {
+    fn increment<E: Hash>(&mut self, e: E) {
+        SlidingFrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        SlidingFrequencySketch::frequency(self, e)
+    }
+}
+
+impl<const N: usize> FrequencyEstimator for StaticFrequencySketch<N> {
+    fn increment<E: Hash>(&mut self, e: E) {
+        StaticFrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        StaticFrequencySketch::frequency(self, e)
+    }
+}
+
+impl<const D: usize> FrequencyEstimator for DepthFrequencySketch<D> {
+    fn increment<E: Hash>(&mut self, e: E) {
+        DepthFrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        DepthFrequencySketch::frequency(self, e)
+    }
+}
+
+impl FrequencyEstimator for HybridFrequencySketch {
+    fn increment<E: Hash>(&mut self, e: E) {
+        HybridFrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        HybridFrequencySketch::frequency(self, e)
+    }
+}
+
+impl FrequencyEstimator for RecencyFrequencySketch {
+    fn increment<E: Hash>(&mut self, e: E) {
+        RecencyFrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        RecencyFrequencySketch::frequency(self, e)
+    }
+}
+
+impl FrequencyEstimator for GenerationalFrequencySketch {
+    fn increment<E: Hash>(&mut self, e: E) {
+        GenerationalFrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        GenerationalFrequencySketch::frequency(self, e)
+    }
+}
+
+impl FrequencyEstimator for ConcurrentFrequencySketch {
+    fn increment<E: Hash>(&mut self, e: E) {
+        ConcurrentFrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        ConcurrentFrequencySketch::frequency(self, e)
+    }
+}
+
+#[cfg(feature = "word32")]
+impl FrequencyEstimator for Word32FrequencySketch {
+    fn increment<E: Hash>(&mut self, e: E) {
+        Word32FrequencySketch::increment(self, e)
+    }
+
+    fn frequency<E: Hash>(&self, e: E) -> u8 {
+        Word32FrequencySketch::frequency(self, e)
+    }
+}
@@ -0,0 +1,133 @@
+use std::cmp::min;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use crate::sketch::frequency_count_sketch::{counted_size, default_hash_code, dimensions_for, rehash, spread};
+
+// Number of generation advances within which every counter is brought up to date
+const SWEEP_GENERATIONS: usize = 8;
+
+/// A variant of <tt>FrequencyCountSketch</tt> whose counters remember the generation they were last
+/// written in, so that aging is applied lazily instead of by sweeping the table. Each counter is a
+/// byte holding a 4-bit count and a 4-bit generation tag. <tt>reset</tt> advances the current
+/// generation, and a count read from an older generation is halved once per generation elapsed, so
+/// stale counts fade on read and a count four or more generations old reads as zero. To keep the
+/// wrapping tags unambiguous, every advance also brings an eighth of the table up to date, bounding
+/// the age of any tag to 8 generations. The table holds as many counters as a
+/// <tt>FrequencyCountSketch</tt> of the same maximum size, at twice the memory.
+#[derive(Debug, Clone)]
+pub struct GenerationalFrequencySketch {
+    // Frequency reduction threshold
+    sample_size: usize,
+    block_mask: usize,
+    // Counters of a 4-bit count in the low nibble and its generation in the high nibble
+    counters: Vec<u8>,
+    size: usize,
+    max_size: usize,
+    generation: u8,
+    // Index of the next slice of counters brought up to date by an advance
+    sweep_cursor: usize,
+}
+
+impl GenerationalFrequencySketch {
+    /// Initializes a sketch that can estimate the popularity of elements given the maximum size of
+    /// the cache, with as many counters as <tt>FrequencyCountSketch::new</tt>.
+    pub fn new(maximum_size: usize) -> Self {
+        let (table_len, maximum, sample_size) = dimensions_for(maximum_size);
+        Self {
+            sample_size,
+            block_mask: (table_len >> 3) - 1,
+            counters: vec![0; table_len * 16],
+            size: 0,
+            max_size: maximum,
+            generation: 0,
+            sweep_cursor: 0,
+        }
+    }
+
+    /// Return max size of this sketch
+    pub fn get_max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return the number of bytes used by the counters.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.counters.len() * size_of::<u8>()
+    }
+
+    /// Return the current generation, between 0 and 15, which wraps around as it advances.
+    pub fn current_generation(&self) -> u8 {
+        self.generation
+    }
+
+    /// Advances the current generation to <tt>generation</tt> modulo 16, one step at a time as with
+    /// <tt>reset</tt>, so that every step halves the counts written before it. Setting the current
+    /// generation is a no-op.
+    pub fn set_generation(&mut self, generation: u8) {
+        while self.generation != generation & 15 {
+            self.reset();
+        }
+    }
+
+    /// Return the estimated number of occurrences of an element in the current generation, up to the
+    /// maximum (15).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        self.positions(default_hash_code(e))
+            .iter()
+            .map(|&i| self.count(self.counters[i]))
+            .fold(15, min)
+    }
+
+    /// Increments the popularity of the element if it does not exceed the maximum (15), bringing its
+    /// counters up to the current generation first. The generation advances when the observed
+    /// events reach the sample size.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        let mut added = false;
+        for i in self.positions(default_hash_code(e)) {
+            let count = self.count(self.counters[i]);
+            added |= count < 15;
+            self.counters[i] = self.tag(min(count + 1, 15));
+        }
+
+        if added {
+            self.size = counted_size(self.size, self.sample_size);
+            if self.size >= self.sample_size {
+                self.reset();
+            }
+        }
+    }
+
+    /// Advances the current generation, which halves every count written before it on read, and
+    /// brings the next eighth of the table up to date.
+    pub fn reset(&mut self) {
+        self.generation = (self.generation + 1) & 15;
+        let slice = self.counters.len() / SWEEP_GENERATIONS;
+        let start = self.sweep_cursor * slice;
+        for i in start..start + slice {
+            self.counters[i] = self.tag(self.count(self.counters[i]));
+        }
+        self.sweep_cursor = (self.sweep_cursor + 1) % SWEEP_GENERATIONS;
+        self.size >>= 1;
+    }
+
+    /// Return the count of the counter as of the current generation.
+    fn count(&self, counter: u8) -> u8 {
+        let age = self.generation.wrapping_sub(counter >> 4) & 15;
+        (counter & 15).checked_shr(age as u32).unwrap_or(0)
+    }
+
+    /// Return a counter holding the count, tagged with the current generation.
+    fn tag(&self, count: u8) -> u8 {
+        self.generation << 4 | count
+    }
+
+    /// Return the index of each of the element's four counters. The block of 128 counters is selected
+    /// like the block of words of <tt>FrequencyCountSketch</tt>, and row <tt>i</tt> selects one of
+    /// the 32 counters of its quarter with one byte of the counter hash.
+    fn positions(&self, hash_code: u64) -> [usize; 4] {
+        let block_hash = spread(hash_code);
+        let counter_hash = rehash(block_hash);
+        let block = (block_hash & self.block_mask) << 7;
+        std::array::from_fn(|i| block + (i << 5) + ((counter_hash >> (i << 3)) & 31))
+    }
+}
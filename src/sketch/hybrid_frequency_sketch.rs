@@ -0,0 +1,89 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::sketch::frequency_count_sketch::{default_hash_code, sample_size_for, FrequencyCountSketch};
+
+/// The largest maximum size for which <tt>HybridFrequencySketch</tt> counts exactly.
+pub const EXACT_MAX_SIZE: usize = 8;
+
+/// A popularity estimator that counts exactly for tiny caches and falls back to a
+/// <tt>FrequencyCountSketch</tt> otherwise. Below the crossover of <tt>EXACT_MAX_SIZE</tt> entries
+/// the sketch would be the minimal 8-word table, whose 128 shared counters are both wasteful and
+/// inaccurate, so the counts are kept per key hash instead. Exact counts saturate at 15 and age
+/// like the sketch: once the sample size of <tt>10 * maximum_size</tt> additions is reached every
+/// count is halved and keys whose count drops to zero are forgotten, which bounds the map by the
+/// sample size. Keys are identified by their <tt>default_hash_code</tt>.
+#[derive(Debug, Clone)]
+pub struct HybridFrequencySketch {
+    counting: Counting,
+}
+
+/// The counting strategy chosen for the maximum size.
+#[derive(Debug, Clone)]
+enum Counting {
+    Exact { counts: HashMap<u64, u8>, size: usize, sample_size: usize },
+    Sketch(Box<FrequencyCountSketch>),
+}
+
+impl HybridFrequencySketch {
+    /// Initializes an estimator for the given maximum size of the cache, counting exactly if it is
+    /// at most <tt>EXACT_MAX_SIZE</tt>.
+    pub fn new(maximum_size: usize) -> Self {
+        let counting = if maximum_size <= EXACT_MAX_SIZE {
+            Counting::Exact { counts: HashMap::new(), size: 0, sample_size: sample_size_for(maximum_size) }
+        } else {
+            Counting::Sketch(Box::new(FrequencyCountSketch::new(maximum_size)))
+        };
+        Self { counting }
+    }
+
+    /// Return whether the estimator counts exactly rather than through a sketch.
+    pub fn is_exact(&self) -> bool {
+        matches!(self.counting, Counting::Exact { .. })
+    }
+
+    /// Increments the popularity of the element if it does not exceed the maximum (15), aging the
+    /// counts when the additions reach the sample size.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        match &mut self.counting {
+            Counting::Exact { counts, size, sample_size } => {
+                let count = counts.entry(default_hash_code(e)).or_insert(0);
+                if *count < 15 {
+                    *count += 1;
+                    *size += 1;
+                    if *size >= *sample_size {
+                        Self::halve(counts, size);
+                    }
+                }
+            }
+            Counting::Sketch(sketch) => sketch.increment(e),
+        }
+    }
+
+    /// Return the number of occurrences of an element, exact in exact mode and estimated otherwise,
+    /// up to the maximum (15).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        match &self.counting {
+            Counting::Exact { counts, .. } => counts.get(&default_hash_code(e)).copied().unwrap_or(0),
+            Counting::Sketch(sketch) => sketch.frequency(e),
+        }
+    }
+
+    /// Reduces every count by half of its original value.
+    pub fn reset(&mut self) {
+        match &mut self.counting {
+            Counting::Exact { counts, size, .. } => Self::halve(counts, size),
+            Counting::Sketch(sketch) => sketch.reset(),
+        }
+    }
+
+    /// Halves the exact counts, forgetting the keys that drop to zero, and the sample accounting.
+    fn halve(counts: &mut HashMap<u64, u8>, size: &mut usize) {
+        counts.retain(|_, count| {
+            *count >>= 1;
+            *count > 0
+        });
+        *size = min(*size >> 1, counts.values().map(|&c| c as usize).sum());
+    }
+}
@@ -1,4 +1,23 @@
+#[cfg(feature = "distinct-count")]
+mod distinct_counter;
+pub mod concurrent_frequency_sketch;
+pub mod depth_frequency_sketch;
+pub mod error;
 pub mod frequency_count_sketch;
+pub mod frequency_count_sketch2;
+pub mod frequency_estimator;
+pub mod generational_frequency_sketch;
+pub mod hybrid_frequency_sketch;
+pub mod recency_frequency_sketch;
+pub mod sketch_pool;
+pub mod sliding_frequency_sketch;
+pub mod sliding_window_sketch;
+pub mod static_frequency_sketch;
+#[cfg(feature = "word32")]
+pub mod word32_frequency_sketch;
+pub mod workload;
 
+#[cfg(test)]
+mod proptests;
 #[cfg(test)]
 mod tests;
\ No newline at end of file
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use proptest::prelude::*;
+
+use crate::sketch::frequency_count_sketch;
+use crate::sketch::frequency_count_sketch::FrequencyCountSketch;
+
+proptest! {
+    #[test]
+    fn prop_bit_count_matches_count_ones(i in any::<u64>()) {
+        prop_assert_eq!(frequency_count_sketch::bit_count(i) as u32, i.count_ones());
+    }
+
+    #[test]
+    fn prop_nibble_sum_word_matches_brute_force(word in any::<u64>()) {
+        let expected: u32 = (0..16).map(|n| ((word >> (n << 2)) & 0xf) as u32).sum();
+        prop_assert_eq!(frequency_count_sketch::nibble_sum_word(word), expected);
+    }
+
+    /// Before any aging occurs the count-min estimate never underestimates the true count, capped
+    /// at the counter maximum.
+    #[test]
+    fn prop_frequency_never_underestimates(
+        maximum_size in 64usize..4096,
+        keys in prop::collection::vec(0u32..512, 0..512),
+    ) {
+        let mut sketch = FrequencyCountSketch::new(maximum_size);
+        let mut counts: HashMap<u32, u8> = HashMap::new();
+        for k in &keys {
+            sketch.increment(k);
+            let c = counts.entry(*k).or_insert(0);
+            *c = (*c + 1).min(15);
+        }
+        for (k, c) in &counts {
+            let f = sketch.frequency(k);
+            prop_assert!(f >= *c, "key {} estimated {} below true count {}", k, f, c);
+            prop_assert!(f <= 15);
+        }
+    }
+
+    /// Aging halves every counter, so an estimate can drop to at least half the prior estimate,
+    /// rounded down, and never rises.
+    #[test]
+    fn prop_reset_halves_estimates(
+        maximum_size in 8usize..8192,
+        keys in prop::collection::vec(0u32..2048, 0..2048),
+    ) {
+        let mut sketch = FrequencyCountSketch::new(maximum_size);
+        for k in &keys {
+            sketch.increment(k);
+        }
+        let before: Vec<u8> = keys.iter().map(|k| sketch.frequency(k)).collect();
+        let size = sketch.estimated_cardinality();
+        sketch.reset();
+        prop_assert!(sketch.estimated_cardinality() <= size);
+        for (k, f) in keys.iter().zip(before) {
+            let after = sketch.frequency(k);
+            prop_assert!(after >= f / 2 && after <= f, "key {} went from {} to {}", k, f, after);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::cmp::{max, min};
+use std::hash::Hash;
+
+use crate::sketch::error::SketchError;
+use crate::sketch::frequency_count_sketch::{derive_positions, spread, FrequencyCountSketch};
+
+/// The largest number of windows within which <tt>RecencyFrequencySketch</tt> can tell a key was
+/// seen, bounded by its 4-bit window tags.
+pub const MAX_RECENT_WINDOWS: usize = 14;
+
+/// A <tt>FrequencyCountSketch</tt> paired with a table of window tags recording when each counter
+/// was last incremented, so that estimates can favor recently active keys. A window is the period
+/// between two resets of the count sketch. Each tag is a nibble laid out like the counters, holding
+/// the index of the latest window, from 1 to 15, in which any key raised the counter, or 0 once
+/// that window has expired. A key idle for the last <tt>recent_windows</tt> windows reports an
+/// estimate of 0 from <tt>frequency_recent</tt>, even though aging alone may take several more
+/// resets to fade its counts. Like the counters, tags are shared by colliding keys, so an idle key
+/// sharing all four counters with active ones still looks recent. The tags double the memory of
+/// the count sketch.
+#[derive(Debug, Clone)]
+pub struct RecencyFrequencySketch {
+    counts: FrequencyCountSketch,
+    // Index of the window in which each counter was last incremented, 0 when expired
+    tags: Vec<u64>,
+    // Index of the current window, from 1 to 15
+    window: u8,
+    recent_windows: usize,
+}
+
+impl RecencyFrequencySketch {
+    /// Initializes a sketch sized like <tt>FrequencyCountSketch::new</tt> whose recent estimates
+    /// cover the last <tt>recent_windows</tt> windows, including the current one. At least one
+    /// window is required, and values above <tt>MAX_RECENT_WINDOWS</tt> are clamped to it.
+    pub fn new(maximum_size: usize, recent_windows: usize) -> Result<Self, SketchError> {
+        if recent_windows == 0 {
+            return Err(SketchError::InvalidWindowCount(recent_windows));
+        }
+        let counts = FrequencyCountSketch::new(maximum_size);
+        let tags = vec![0; counts.raw_table().len()];
+        Ok(Self { counts, tags, window: 1, recent_windows: min(recent_windows, MAX_RECENT_WINDOWS) })
+    }
+
+    /// Return the underlying count sketch.
+    pub fn counts(&self) -> &FrequencyCountSketch {
+        &self.counts
+    }
+
+    /// Return the index of the current window, from 1 to 15, which wraps around as it advances.
+    pub fn current_window(&self) -> u8 {
+        self.window
+    }
+
+    /// Increments the popularity of the element and tags its counters with the current window. A
+    /// reset of the count sketch when the sample size is reached starts the next window.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        let hash_code = self.counts.hash_code(e);
+        for (word, nibble) in self.positions(hash_code) {
+            let shift = nibble << 2;
+            self.tags[word] = (self.tags[word] & !(0xf << shift)) | (self.window as u64) << shift;
+        }
+        let resets = self.counts.reset_count();
+        self.counts.increment_hashed(hash_code);
+        if self.counts.reset_count() != resets {
+            self.advance_window();
+        }
+    }
+
+    /// Return the estimated frequency of the element, like <tt>FrequencyCountSketch::frequency</tt>,
+    /// regardless of when it was last seen.
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        self.counts.frequency(e)
+    }
+
+    /// Return the estimated frequency of the element if it was seen within the recent windows, and
+    /// 0 otherwise. A key counts as seen when none of its counters' tags is older than the recent
+    /// windows, as every counter of a key is tagged when it is incremented.
+    pub fn frequency_recent<E: Hash>(&self, e: E) -> u8 {
+        let hash_code = self.counts.hash_code(e);
+        let oldest = self.positions(hash_code).iter().fold(0, |oldest, &(word, nibble)| {
+            let tag = ((self.tags[word] >> (nibble << 2)) & 0xf) as u8;
+            max(oldest, if tag == 0 { u8::MAX } else { self.age(tag) })
+        });
+        if (oldest as usize) < self.recent_windows {
+            self.counts.frequency_hashed(hash_code)
+        } else {
+            0
+        }
+    }
+
+    /// Halves every counter like <tt>FrequencyCountSketch::reset</tt> and starts the next window.
+    pub fn reset(&mut self) {
+        self.counts.reset();
+        self.advance_window();
+    }
+
+    /// Moves on to the next window index, expiring the tags that would otherwise become ambiguous
+    /// once it wraps around to their index.
+    fn advance_window(&mut self) {
+        self.window = self.window % 15 + 1;
+        let expired = self.window as u64;
+        for word in self.tags.iter_mut() {
+            for n in 0..16 {
+                if (*word >> (n << 2)) & 0xf == expired {
+                    *word &= !(0xf << (n << 2));
+                }
+            }
+        }
+    }
+
+    /// Return how many windows ago the window with the given non-zero tag was current.
+    fn age(&self, tag: u8) -> u8 {
+        (self.window + 15 - tag) % 15
+    }
+
+    /// Return the table word and nibble of each of the element's counters, which the tags share.
+    fn positions(&self, hash_code: u64) -> [(usize, usize); 4] {
+        let block_shift = self.counts.block_words().trailing_zeros();
+        let (words, nibbles) = derive_positions(self.counts.block_mask(), block_shift, spread(hash_code));
+        std::array::from_fn(|i| (words[i], nibbles[i]))
+    }
+}
@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+
+use crate::sketch::frequency_count_sketch::{dimensions_for, FrequencyCountSketch};
+
+/// A pool recycling <tt>FrequencyCountSketch</tt> instances of one maximum size, e.g. for
+/// short-lived per-request sketches whose tables would otherwise be allocated and freed on every
+/// request. A sketch returned by <tt>acquire</tt> goes back to the pool when it is dropped, cleared
+/// in place by <tt>clear</tt> so that its table is reused as is, and with its reset hook removed so
+/// that the next caller does not inherit it. A sketch whose table was resized, e.g. by
+/// <tt>compact</tt> or <tt>shrink_to</tt>, is discarded instead, so the pool only ever hands out
+/// sketches of its own dimensions. Other settings changed on a pooled sketch, such as the aging
+/// mode, survive its return to the pool. The pool is not <tt>Sync</tt>; keep one per thread, e.g.
+/// in a <tt>thread_local!</tt>.
+#[derive(Debug)]
+pub struct SketchPool {
+    maximum_size: usize,
+    // Cleared sketches ready to be handed out
+    free: RefCell<Vec<FrequencyCountSketch>>,
+}
+
+impl SketchPool {
+    /// Creates an empty pool of sketches created by <tt>FrequencyCountSketch::new(maximum_size)</tt>.
+    pub fn new(maximum_size: usize) -> Self {
+        Self { maximum_size, free: RefCell::new(Vec::new()) }
+    }
+
+    /// Return an empty sketch, recycled from the pool if one is available and newly created
+    /// otherwise.
+    pub fn acquire(&self) -> PooledSketch<'_> {
+        let sketch = self.free.borrow_mut().pop().unwrap_or_else(|| FrequencyCountSketch::new(self.maximum_size));
+        PooledSketch { pool: self, sketch: Some(sketch) }
+    }
+
+    /// Return the number of sketches waiting in the pool to be acquired.
+    pub fn available(&self) -> usize {
+        self.free.borrow().len()
+    }
+}
+
+/// A sketch borrowed from a <tt>SketchPool</tt>, dereferencing to the <tt>FrequencyCountSketch</tt>
+/// and returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledSketch<'a> {
+    pool: &'a SketchPool,
+    // Always present until the sketch is returned on drop
+    sketch: Option<FrequencyCountSketch>,
+}
+
+impl Deref for PooledSketch<'_> {
+    type Target = FrequencyCountSketch;
+
+    fn deref(&self) -> &FrequencyCountSketch {
+        self.sketch.as_ref().expect("pooled sketch already returned")
+    }
+}
+
+impl DerefMut for PooledSketch<'_> {
+    fn deref_mut(&mut self) -> &mut FrequencyCountSketch {
+        self.sketch.as_mut().expect("pooled sketch already returned")
+    }
+}
+
+impl Drop for PooledSketch<'_> {
+    fn drop(&mut self) {
+        if let Some(mut sketch) = self.sketch.take() {
+            let (table_len, maximum, _) = dimensions_for(self.pool.maximum_size);
+            if sketch.get_table_len() != table_len || sketch.get_max_size() != maximum {
+                return;
+            }
+            sketch.clear();
+            sketch.remove_reset_hook();
+            self.pool.free.borrow_mut().push(sketch);
+        }
+    }
+}
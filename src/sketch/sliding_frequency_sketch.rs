@@ -0,0 +1,58 @@
+use std::cmp::min;
+use std::hash::Hash;
+use std::mem;
+
+use crate::sketch::frequency_count_sketch::{default_hash_code, FrequencyCountSketch};
+
+/// Estimates popularity over two consecutive windows of the access stream, e.g. to detect trends.
+/// Increments are counted in the current window until <tt>rotate</tt> closes it: the current window
+/// then becomes the previous one, replacing it, and a new empty window starts. The frequency of an
+/// element blends both windows, counting its occurrences in the previous window at half weight, so
+/// a key that has gone cold fades out over one rotation instead of disappearing at once. Each
+/// window is a <tt>FrequencyCountSketch</tt> and is still aged by its own resets.
+#[derive(Debug, Clone)]
+pub struct SlidingFrequencySketch {
+    current: FrequencyCountSketch,
+    previous: FrequencyCountSketch,
+}
+
+impl SlidingFrequencySketch {
+    /// Creates a sliding sketch whose windows are each sized like <tt>FrequencyCountSketch::new</tt>.
+    pub fn new(maximum_size: usize) -> Self {
+        Self {
+            current: FrequencyCountSketch::new(maximum_size),
+            previous: FrequencyCountSketch::new(maximum_size),
+        }
+    }
+
+    /// Increments the popularity of the element in the current window.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        self.current.increment(e);
+    }
+
+    /// Return the estimated number of occurrences of an element in the current window plus half of
+    /// those in the previous window, up to the maximum (15).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        let hash_code = default_hash_code(e);
+        let current = self.current.frequency_hashed(hash_code);
+        let previous = self.previous.frequency_hashed(hash_code);
+        min(current + (previous >> 1), 15)
+    }
+
+    /// Closes the current window, making it the previous one, and starts an empty current window.
+    /// The counts of the old previous window are discarded.
+    pub fn rotate(&mut self) {
+        let fresh = FrequencyCountSketch::new(self.current.get_max_size());
+        self.previous = mem::replace(&mut self.current, fresh);
+    }
+
+    /// Return the sketch counting the current window.
+    pub fn current(&self) -> &FrequencyCountSketch {
+        &self.current
+    }
+
+    /// Return the sketch holding the counts of the previous window.
+    pub fn previous(&self) -> &FrequencyCountSketch {
+        &self.previous
+    }
+}
@@ -0,0 +1,65 @@
+use std::hash::Hash;
+
+use crate::sketch::error::SketchError;
+use crate::sketch::frequency_count_sketch::FrequencyCountSketch;
+
+/// Estimates popularity over a sliding window of the access stream made of a ring of sub-windows.
+/// Increments are counted in the newest sub-window until <tt>advance</tt> moves on: the oldest
+/// sub-window is then cleared and becomes the newest, so the counts of a sub-window expire exactly
+/// after as many advances as there are sub-windows. Unlike the aging of a single sketch, which only
+/// fades old counts, this bounds the history that contributes to an estimate, at the memory cost of
+/// one <tt>FrequencyCountSketch</tt> per sub-window. The sub-windows never age on their own, so
+/// each counts up to its maximum (15) between advances.
+#[derive(Debug, Clone)]
+pub struct SlidingWindowSketch {
+    windows: Vec<FrequencyCountSketch>,
+    // Index of the sub-window receiving the increments
+    newest: usize,
+}
+
+impl SlidingWindowSketch {
+    /// Creates a sliding window sketch with the given number of sub-windows, each sized like
+    /// <tt>FrequencyCountSketch::new</tt>. At least one sub-window is required.
+    pub fn new(maximum_size: usize, windows: usize) -> Result<Self, SketchError> {
+        if windows == 0 {
+            return Err(SketchError::InvalidWindowCount(windows));
+        }
+        let window = Self::window(maximum_size);
+        Ok(Self { windows: vec![window; windows], newest: 0 })
+    }
+
+    /// Return the number of sub-windows.
+    pub fn window_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// Increments the popularity of the element in the newest sub-window.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        self.windows[self.newest].increment(e);
+    }
+
+    /// Return the estimated number of occurrences of an element summed over all sub-windows, up to
+    /// 15 per sub-window.
+    pub fn frequency<E: Hash>(&self, e: E) -> u32 {
+        let hash_code = self.windows[self.newest].hash_code(e);
+        self.windows.iter().map(|window| window.frequency_hashed(hash_code) as u32).sum()
+    }
+
+    /// Expires the oldest sub-window, clearing its counts, and starts counting in it as the newest.
+    pub fn advance(&mut self) {
+        self.newest = (self.newest + 1) % self.windows.len();
+        self.windows[self.newest] = Self::window(self.windows[self.newest].get_max_size());
+    }
+
+    /// Return the sketch counting the newest sub-window.
+    pub fn newest(&self) -> &FrequencyCountSketch {
+        &self.windows[self.newest]
+    }
+
+    /// Creates an empty sub-window that is only cleared by <tt>advance</tt>.
+    fn window(maximum_size: usize) -> FrequencyCountSketch {
+        let mut window = FrequencyCountSketch::new(maximum_size);
+        window.set_aging_enabled(false);
+        window
+    }
+}
@@ -0,0 +1,98 @@
+use std::cmp::min;
+use std::hash::Hash;
+
+use crate::sketch::frequency_count_sketch::{
+    counted_size, default_hash_code, halve_table, rehash, sample_size_for, spread, CounterWord,
+};
+
+/// A <tt>FrequencyCountSketch</tt> whose table length <tt>N</tt> is fixed at compile time, so the
+/// table is an inline array that can live on the stack or in static memory without any heap
+/// allocation, e.g. on embedded targets. It uses the same 4-bit counters, block layout and aging as
+/// <tt>FrequencyCountSketch::new(N)</tt>, so both estimate identical frequencies. <tt>N</tt> must be
+/// a power of two of at least 8; other lengths fail to compile.
+#[derive(Debug, Clone)]
+pub struct StaticFrequencySketch<const N: usize> {
+    // Frequency reduction threshold
+    sample_size: usize,
+    // Access frequency container
+    table: [u64; N],
+    size: usize,
+}
+
+impl<const N: usize> StaticFrequencySketch<N> {
+    // Evaluated when the sketch is instantiated, rejecting unsupported lengths at compile time
+    const VALID_LEN: () = assert!(N.is_power_of_two() && N >= 8, "table length must be a power of two >= 8");
+    const BLOCK_MASK: usize = (N >> 3) - 1;
+
+    /// Initializes an empty sketch for a cache holding up to <tt>N</tt> entries. As a
+    /// <tt>const fn</tt>, it can initialize a constant or a static without running any code.
+    pub const fn new() -> Self {
+        let () = Self::VALID_LEN;
+        Self {
+            sample_size: sample_size_for(N),
+            table: [0; N],
+            size: 0,
+        }
+    }
+
+    /// Return table len of this sketch
+    pub fn get_table_len(&self) -> usize {
+        N
+    }
+
+    /// Return the estimated number of occurrences of an element, up to the maximum (15).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        let mut count:[u8; 4] = [0; 4];
+        let (block, counter_hash) = self.block_of(default_hash_code(e));
+        for (i, c) in count.iter_mut().enumerate() {
+            let h = counter_hash >> (i << 3);
+            let index = (h >> 1) & 15;
+            let offset = h & 1;
+            *c = ((self.table[block + offset + (i << 1)] >> (index << 2)) & 0xf) as u8;
+        }
+        min(min(count[0], count[1]), min(count[2], count[3]))
+    }
+
+    /// Increments the popularity of the element if it does not exceed the maximum (15). The popularity
+    /// of all elements will be periodically down sampled when the observed events exceed a threshold.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        let (block, counter_hash) = self.block_of(default_hash_code(e));
+        let mut added = false;
+        for i in 0..4 {
+            let h = counter_hash >> (i << 3);
+            let index = (h >> 1) & 15;
+            let offset = h & 1;
+            added |= self.increment_at(block + offset + (i << 1), index);
+        }
+
+        if added {
+            self.size = counted_size(self.size, self.sample_size);
+            if self.size >= self.sample_size {
+                self.reset();
+            }
+        }
+    }
+
+    /// Reduces every counter by half of its original value.
+    pub fn reset(&mut self) {
+        self.size = halve_table(&mut self.table, self.size);
+    }
+
+    /// Returns the start of the element's block and the hash used to select its counters.
+    fn block_of(&self, hash_code: u64) -> (usize, usize) {
+        let block_hash = spread(hash_code);
+        let counter_hash = rehash(block_hash);
+        ((block_hash & Self::BLOCK_MASK) << 3, counter_hash)
+    }
+
+    /// Increments the specified counter by 1 if it is not already at the maximum value (15).
+    fn increment_at(&mut self, i: usize, j: usize) -> bool {
+        self.table[i].increment_nibble(j)
+    }
+}
+
+impl<const N: usize> Default for StaticFrequencySketch<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
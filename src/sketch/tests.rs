@@ -1,5 +1,27 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use crate::sketch::concurrent_frequency_sketch::ConcurrentFrequencySketch;
+use crate::sketch::depth_frequency_sketch::DepthFrequencySketch;
+use crate::sketch::error::SketchError;
 use crate::sketch::frequency_count_sketch;
-use crate::sketch::frequency_count_sketch::FrequencyCountSketch;
+use crate::sketch::frequency_count_sketch::{FrequencyCountSketch, SketchMerger};
+use crate::sketch::frequency_count_sketch2::FrequencyCountSketch2;
+use crate::sketch::frequency_estimator::FrequencyEstimator;
+use crate::sketch::generational_frequency_sketch::GenerationalFrequencySketch;
+use crate::sketch::hybrid_frequency_sketch::HybridFrequencySketch;
+use crate::sketch::recency_frequency_sketch::RecencyFrequencySketch;
+use crate::sketch::sketch_pool::SketchPool;
+use crate::sketch::sliding_frequency_sketch::SlidingFrequencySketch;
+use crate::sketch::sliding_window_sketch::SlidingWindowSketch;
+use crate::sketch::static_frequency_sketch::StaticFrequencySketch;
+#[cfg(feature = "word32")]
+use crate::sketch::word32_frequency_sketch::Word32FrequencySketch;
+use crate::sketch::workload;
 
 #[test]
 fn test_bit_count() {
@@ -7,6 +29,59 @@ fn test_bit_count() {
     println!("{}", frequency_count_sketch::bit_count(i))
 }
 
+#[test]
+fn test_nibble_sum_word() {
+    for word in [0u64, u64::MAX, 0x1111111111111111, 0xf0f0f0f0f0f0f0f0, 0x123456789abcdef0, 1 << 63] {
+        let expected: u32 = (0..16).map(|n| ((word >> (n << 2)) & 0xf) as u32).sum();
+        assert_eq!(frequency_count_sketch::nibble_sum_word(word), expected);
+    }
+    assert_eq!(frequency_count_sketch::nibble_sum_word(u64::MAX), 240);
+
+    // The mass of a saturated table accumulates past the range of a single word's sum
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.set_aging_enabled(false);
+    for i in 0..20000 {
+        sketch.increment(i);
+    }
+    assert_eq!(sketch.total_mass(), sketch.decode_counters().iter().map(|&c| c as u64).sum::<u64>());
+}
+
+#[test]
+fn test_counter_word() {
+    use crate::sketch::frequency_count_sketch::CounterWord;
+
+    // Both word widths saturate each nibble at 15 without touching its neighbours
+    let mut wide = 0u64;
+    let mut narrow = 0u32;
+    for _ in 0..20 {
+        wide.increment_nibble(15);
+        narrow.increment_nibble(7);
+    }
+    assert!(!wide.increment_nibble(15) && !narrow.increment_nibble(7));
+    assert_eq!((wide, narrow), (0xf << 60, 0xf << 28));
+    assert!(wide.increment_nibble(0) && narrow.increment_nibble(0));
+
+    // Halving keeps the high bits of each counter and counts the odd ones
+    assert_eq!(wide.halve_nibbles(), 2);
+    assert_eq!(narrow.halve_nibbles(), 2);
+    assert_eq!((wide, narrow), (0x7 << 60, 0x7 << 28));
+    let mut pairs = 0u64;
+    for _ in 0..5 {
+        pairs.increment_counter(62, 0x3);
+    }
+    pairs.increment_counter(0, 0x3);
+    assert_eq!(pairs.halve_counters(0x5555555555555555, 0x5555555555555555), 2);
+    assert_eq!(pairs, 1 << 62);
+
+    // The accounting never underflows however many counters were odd, nor overflows the sample
+    let mut table = [u64::MAX; 8];
+    assert_eq!(frequency_count_sketch::halve_table(&mut table, 10), 0);
+    assert_eq!(table, [0x7777777777777777; 8]);
+    assert_eq!(frequency_count_sketch::halved_size(1000, 8), 499);
+    assert_eq!(frequency_count_sketch::counted_size(usize::MAX, usize::MAX), usize::MAX);
+    assert_eq!(frequency_count_sketch::counted_size(6, 7), 7);
+}
+
 #[test]
 fn test_number_of_leading_zeros() {
     let i = 89;
@@ -51,4 +126,2600 @@ fn test_frequency() {
 
     let f = counter.frequency(a);
     println!("{}", f)
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_with_table_len() {
+    for table_len in [8usize, 16, 1024, 1 << 20] {
+        let sketch = FrequencyCountSketch::with_table_len(table_len).unwrap();
+        assert_eq!(sketch.get_table_len(), table_len);
+        assert_eq!(sketch.get_max_size(), table_len);
+    }
+
+    let mut sketch = FrequencyCountSketch::with_table_len(64).unwrap();
+    sketch.increment(1);
+    assert_eq!(sketch.frequency(1), 1);
+}
+
+#[test]
+fn test_with_table_len_rejects_invalid() {
+    for table_len in [0usize, 1, 4, 12, 100, 1000, (1 << 30) + 1] {
+        assert_eq!(
+            FrequencyCountSketch::with_table_len(table_len).unwrap_err(),
+            SketchError::InvalidTableLength(table_len)
+        );
+    }
+}
+
+#[test]
+fn test_capacity_and_counter_count() {
+    for (maximum_size, table_len) in [(0usize, 8usize), (5, 8), (20, 32), (1000, 1024), (1024, 1024)] {
+        let sketch = FrequencyCountSketch::new(maximum_size);
+        assert_eq!(sketch.capacity(), maximum_size);
+        assert_eq!(sketch.capacity(), sketch.get_max_size());
+        assert_eq!(sketch.get_table_len(), table_len);
+        assert_eq!(sketch.counter_count(), sketch.get_table_len() * 16);
+    }
+}
+
+#[test]
+fn test_estimated_cardinality() {
+    let mut sketch = FrequencyCountSketch::new(4096);
+    assert_eq!(sketch.estimated_cardinality(), 0);
+    assert_eq!(sketch.linear_counting_estimate(), 0.0);
+
+    for i in 0..200 {
+        sketch.increment(i);
+    }
+    assert_eq!(sketch.estimated_cardinality(), 200);
+    let estimate = sketch.linear_counting_estimate();
+    assert!((180.0..=220.0).contains(&estimate), "estimate {}", estimate);
+
+    // Repeated keys do not move the linear counting estimate
+    for i in 0..200 {
+        sketch.increment(i);
+    }
+    assert_eq!(sketch.estimated_cardinality(), 400);
+    assert_eq!(sketch.linear_counting_estimate(), estimate);
+}
+
+#[cfg(feature = "debug-invariants")]
+#[test]
+fn test_validate() {
+    let mut sketch = FrequencyCountSketch::new(16);
+    assert_eq!(sketch.validate(), Ok(()));
+
+    for i in 0..2000 {
+        sketch.increment(i % 37);
+        assert_eq!(sketch.validate(), Ok(()));
+    }
+    sketch.reset();
+    assert_eq!(sketch.validate(), Ok(()));
+}
+
+#[test]
+fn test_frequency_scored() {
+    let mut isolated = FrequencyCountSketch::new(4096);
+    assert_eq!(isolated.frequency_scored(1), (0, 1.0));
+    isolated.increment(1);
+    isolated.increment(1);
+    assert_eq!(isolated.frequency_scored(1), (2, 1.0));
+
+    // A single block shared with many hot keys inflates some of the key's counters
+    let mut crowded = FrequencyCountSketch::new(8);
+    for i in 100..108 {
+        for _ in 0..8 {
+            crowded.increment(i);
+        }
+    }
+    crowded.increment(1);
+    let (frequency, score) = crowded.frequency_scored(1);
+    assert_eq!(frequency, crowded.frequency(1));
+    assert!(score < 1.0, "score {}", score);
+}
+
+#[test]
+fn test_two_bit_sketch_saturates_at_three() {
+    let mut sketch = FrequencyCountSketch2::new(1024);
+    assert_eq!(sketch.frequency(1), 0);
+    for expected in 1..=3 {
+        sketch.increment(1);
+        assert_eq!(sketch.frequency(1), expected);
+    }
+    for _ in 0..10 {
+        sketch.increment(1);
+    }
+    assert_eq!(sketch.frequency(1), 3);
+}
+
+#[test]
+fn test_two_bit_sketch_placement() {
+    let mut sketch = FrequencyCountSketch2::new(1024);
+    for i in 0..100 {
+        sketch.increment(i);
+    }
+    for i in 0..100 {
+        assert!(sketch.frequency(i) >= 1);
+    }
+    let untouched = (1000..1100).filter(|i| sketch.frequency(i) == 0).count();
+    assert!(untouched > 90, "untouched {}", untouched);
+
+    sketch.increment(1);
+    sketch.increment(1);
+    sketch.reset();
+    assert_eq!(sketch.frequency(1), 1);
+}
+
+#[test]
+fn test_two_bit_sketch_memory_usage() {
+    for maximum_size in [16usize, 1024, 100_000] {
+        let sketch2 = FrequencyCountSketch2::new(maximum_size);
+        let sketch4 = FrequencyCountSketch::new(maximum_size);
+        assert_eq!(sketch2.memory_usage_bytes() * 2, sketch4.memory_usage_bytes());
+    }
+    assert_eq!(FrequencyCountSketch2::new(4).memory_usage_bytes(), 64);
+}
+
+#[test]
+fn test_aggressive_aging() {
+    for aggressive in [false, true] {
+        let mut sketch = FrequencyCountSketch::new(1024);
+        sketch.set_aggressive_aging(aggressive);
+        for _ in 0..20 {
+            sketch.increment("old");
+        }
+        assert_eq!(sketch.frequency("old"), 15);
+
+        let mut resets = 0;
+        while sketch.frequency("old") >= sketch.frequency("new") {
+            for _ in 0..8 {
+                sketch.increment("new");
+            }
+            sketch.reset();
+            resets += 1;
+            assert!(resets < 10);
+        }
+        assert_eq!(resets, if aggressive { 1 } else { 2 });
+    }
+}
+
+#[test]
+fn test_partial_eq() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.increment(1);
+    sketch.increment(2);
+
+    let mut copy = sketch.clone();
+    assert_eq!(copy, sketch);
+
+    copy.increment(3);
+    assert_ne!(copy, sketch);
+    assert_ne!(FrequencyCountSketch::new(64), FrequencyCountSketch::new(128));
+}
+
+#[test]
+fn test_on_reset_hook() {
+    let resets = Arc::new(Mutex::new(0u32));
+    let mut sketch = FrequencyCountSketch::new(8);
+    let counter = resets.clone();
+    sketch.on_reset(move |s| {
+        assert!(s.estimated_cardinality() < 80);
+        *counter.lock().unwrap() += 1;
+    });
+
+    sketch.reset();
+    assert_eq!(*resets.lock().unwrap(), 1);
+
+    // new(8) ages after 80 increments that change a counter
+    for i in 0..79 {
+        sketch.increment(i);
+    }
+    assert_eq!(*resets.lock().unwrap(), 1);
+    let mut i = 79;
+    while *resets.lock().unwrap() == 1 {
+        sketch.increment(i);
+        i += 1;
+    }
+    assert_eq!(*resets.lock().unwrap(), 2);
+}
+
+#[test]
+fn test_sketches_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn assert_send<T: Send>() {}
+    assert_send_sync::<FrequencyCountSketch>();
+    assert_send_sync::<ConcurrentFrequencySketch>();
+    assert_send_sync::<GenerationalFrequencySketch>();
+    assert_send_sync::<HybridFrequencySketch>();
+    assert_send_sync::<RecencyFrequencySketch>();
+    assert_send_sync::<SlidingFrequencySketch>();
+    assert_send_sync::<SlidingWindowSketch>();
+    assert_send::<SketchPool>();
+
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.on_reset(|_| {});
+    std::thread::spawn(move || sketch.reset()).join().unwrap();
+}
+
+#[test]
+fn test_relative_frequency() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    assert_eq!(sketch.relative_frequency(1), 0.0);
+    assert_eq!(sketch.relative_to_peak(1), 0.0);
+
+    for _ in 0..3 {
+        sketch.increment(1);
+    }
+    for _ in 0..6 {
+        sketch.increment(2);
+    }
+    assert_eq!(sketch.relative_frequency(1), 3.0 / 15.0);
+    assert_eq!(sketch.relative_frequency(2), 6.0 / 15.0);
+    assert_eq!(sketch.relative_to_peak(1), 0.5);
+    assert_eq!(sketch.relative_to_peak(2), 1.0);
+
+    for _ in 0..20 {
+        sketch.increment(2);
+    }
+    assert_eq!(sketch.relative_frequency(2), 1.0);
+}
+
+#[test]
+fn test_compact() {
+    let mut sketch = FrequencyCountSketch::new(1 << 16);
+    sketch.increment(1);
+    assert!(sketch.compact(100));
+    assert_eq!(sketch.get_table_len(), 128);
+    assert_eq!(sketch.capacity(), 100);
+    assert_eq!(sketch.frequency(1), 0);
+    assert_eq!(sketch, FrequencyCountSketch::new(100));
+
+    // Already sized for the workload
+    let mut sketch = FrequencyCountSketch::new(100);
+    sketch.increment(1);
+    assert!(!sketch.compact(100));
+    assert!(!sketch.compact(40));
+    assert_eq!(sketch.get_table_len(), 128);
+    assert_eq!(sketch.frequency(1), 1);
+    assert!(sketch.compact(20));
+    assert_eq!(sketch.get_table_len(), 32);
+}
+
+#[test]
+fn test_decay() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    for _ in 0..12 {
+        sketch.increment(1);
+    }
+    for _ in 0..5 {
+        sketch.increment(2);
+    }
+    let mut halved = sketch.clone();
+    halved.reset();
+
+    let mut decayed = sketch.clone();
+    decayed.decay(1, 2);
+    assert_eq!(decayed, halved);
+    assert_eq!(decayed.frequency(1), 6);
+    assert_eq!(decayed.frequency(2), 2);
+
+    // Many odd counters make the accounting of a plain scaling drift from the one of reset
+    let mut crowded = FrequencyCountSketch::new(1024);
+    for i in 0..300u32 {
+        crowded.increment(i);
+    }
+    let mut halved = crowded.clone();
+    halved.reset();
+    crowded.decay(1, 2);
+    assert_eq!(crowded.estimated_cardinality(), halved.estimated_cardinality());
+    assert_eq!(crowded, halved);
+
+    // The explicit factor applies whatever the sketch's own aging does
+    let mut slow = FrequencyCountSketch::new_with_decay(1024, 0.9).unwrap();
+    slow.increment_by(1, 15);
+    slow.decay(1, 2);
+    assert_eq!(slow.frequency(1), 7);
+    let mut aggressive = FrequencyCountSketch::new(1024);
+    aggressive.set_aggressive_aging(true);
+    aggressive.increment_by(1, 15);
+    aggressive.decay(1, 2);
+    assert_eq!(aggressive.frequency(1), 7);
+    aggressive.reset();
+    assert_eq!(aggressive.frequency(1), 3);
+
+    let mut gentle = sketch.clone();
+    gentle.decay(3, 4);
+    assert_eq!(gentle.frequency(1), 9);
+    assert_eq!(gentle.frequency(2), 3);
+    assert_eq!(gentle.estimated_cardinality(), 17 * 3 / 4);
+
+    // Factors above one saturate instead of overflowing into neighboring counters
+    let mut grown = sketch.clone();
+    grown.decay(2, 1);
+    assert_eq!(grown.frequency(1), 15);
+    assert_eq!(grown.frequency(2), 10);
+}
+
+#[test]
+fn test_block_mask_and_sample_size() {
+    for maximum_size in [0usize, 1, 8, 20, 1000, 1 << 16] {
+        let sketch = FrequencyCountSketch::new(maximum_size);
+        assert_eq!(sketch.block_mask(), (sketch.get_table_len() >> 3) - 1);
+        assert_eq!(sketch.sample_size(), 10 * maximum_size.max(1));
+    }
+}
+
+#[test]
+fn test_increment_checked() {
+    let mut sketch = FrequencyCountSketch::new(8);
+    let sample_size = sketch.sample_size();
+    for i in 0..sample_size - 1 {
+        assert!(!sketch.increment_checked(i));
+    }
+    assert_eq!(sketch.estimated_cardinality(), sample_size - 1);
+    assert!(sketch.increment_checked(sample_size));
+    assert!(sketch.estimated_cardinality() < sample_size / 2 + 1);
+}
+
+#[test]
+fn test_increment_by() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_by(1, 5);
+    assert_eq!(sketch.frequency(1), 5);
+    assert_eq!(sketch.estimated_cardinality(), 5);
+    sketch.increment_by(1, 100);
+    assert_eq!(sketch.frequency(1), 15);
+    assert_eq!(sketch.estimated_cardinality(), 15);
+
+    let mut sketch = FrequencyCountSketch::new(1);
+    let mut expected = FrequencyCountSketch::new(1);
+    sketch.increment_by(2, 12);
+    for _ in 0..12 {
+        expected.increment(2);
+    }
+    assert_eq!(sketch, expected);
+}
+
+#[test]
+fn test_load_histogram() {
+    let mut histogram = HashMap::new();
+    histogram.insert("a", 5u32);
+    histogram.insert("b", 1u32);
+
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.load_histogram(histogram);
+    assert!(sketch.frequency("a") >= sketch.frequency("b"));
+    assert_eq!(sketch.frequency("a"), 5);
+    assert_eq!(sketch.frequency("b"), 1);
+
+    sketch.load_histogram([("a", 40)]);
+    assert_eq!(sketch.frequency("a"), 15);
+}
+
+#[test]
+fn test_frequency_saturating() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_by(1, 14);
+    assert_eq!(sketch.frequency_saturating(1), (14, false));
+    sketch.increment_by(1, 5);
+    assert_eq!(sketch.frequency_saturating(1), (15, true));
+    assert_eq!(sketch.frequency_saturating(2), (0, false));
+}
+
+#[test]
+fn test_decode_counters() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    let counters = sketch.decode_counters();
+    assert_eq!(counters.len(), sketch.counter_count());
+    assert!(counters.iter().all(|&c| c == 0));
+
+    sketch.increment(1);
+    let counters = sketch.decode_counters();
+    assert_eq!(counters.iter().filter(|&&c| c == 1).count(), 4);
+    assert_eq!(counters.iter().filter(|&&c| c != 0).count(), 4);
+    for position in sketch.counter_positions(1) {
+        assert_eq!(counters[position], 1);
+    }
+
+    sketch.increment_by(2, 40);
+    let counters = sketch.decode_counters();
+    assert_eq!(counters.len(), sketch.get_table_len() * 16);
+    assert!(counters.iter().all(|&c| c <= 15));
+    for position in sketch.counter_positions(2) {
+        assert_eq!(counters[position], 15);
+    }
+}
+
+#[test]
+fn test_locate() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    let (words, nibbles) = sketch.locate("key");
+    for i in 0..4 {
+        assert!(words[i] < sketch.get_table_len());
+        assert!(nibbles[i] < 16);
+        // Every counter lives in its own 16-byte segment of the same 64-byte block
+        assert_eq!(words[i] >> 3, words[0] >> 3);
+        assert_eq!((words[i] & 7) >> 1, i);
+    }
+
+    sketch.increment_by("key", 3);
+    let counters = sketch.decode_counters();
+    for i in 0..4 {
+        assert_eq!(counters[words[i] * 16 + nibbles[i]], 3);
+    }
+    assert_eq!(counters.iter().filter(|&&c| c != 0).count(), 4);
+}
+
+#[test]
+fn test_bytes_keys() {
+    let short: &[u8] = b"ab";
+    let long: &[u8] = b"abc";
+    assert_ne!(frequency_count_sketch::bytes_hash_code(short), frequency_count_sketch::bytes_hash_code(long));
+    assert_eq!(frequency_count_sketch::bytes_hash_code(short), frequency_count_sketch::bytes_hash_code(b"ab"));
+    assert_ne!(frequency_count_sketch::bytes_hash_code(short), frequency_count_sketch::default_hash_code(short));
+
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_bytes(short);
+    sketch.increment_bytes(short);
+    sketch.increment_bytes(long);
+    assert_eq!(sketch.frequency_bytes(short), 2);
+    assert_eq!(sketch.frequency_bytes(long), 1);
+    assert_eq!(sketch.frequency_bytes(b"a"), 0);
+    // The byte path shares the derivation of the hashed path
+    assert_eq!(sketch.frequency_hashed(frequency_count_sketch::bytes_hash_code(long)), 1);
+}
+
+#[test]
+fn test_new_adaptive() {
+    let mut sketch = FrequencyCountSketch::new_adaptive(64);
+    let base = FrequencyCountSketch::new(64).sample_size();
+    assert_eq!(sketch.sample_size(), base);
+
+    // A burst of distinct keys crowds the table, so the sketch ages more often
+    for i in 0..20_000 {
+        sketch.increment(i);
+        assert!(sketch.sample_size() >= base / 4 && sketch.sample_size() <= base * 4);
+    }
+    assert_eq!(sketch.sample_size(), base / 4);
+
+    // A few repeated keys leave most of the table empty, so the window grows again
+    for i in 0..20_000 {
+        sketch.increment(i % 16);
+        assert!(sketch.sample_size() >= base / 4 && sketch.sample_size() <= base * 4);
+    }
+    assert!(sketch.sample_size() > base / 4);
+
+    let mut fixed = FrequencyCountSketch::new(64);
+    for i in 0..20_000 {
+        fixed.increment(i);
+    }
+    assert_eq!(fixed.sample_size(), base);
+}
+
+#[test]
+fn test_table_len_floor() {
+    for maximum_size in [0usize, 1, 3, 8] {
+        assert_eq!(FrequencyCountSketch::new(maximum_size).get_table_len(), frequency_count_sketch::MIN_TABLE_LEN);
+    }
+    let sketch = FrequencyCountSketch::with_table_len(frequency_count_sketch::MIN_TABLE_LEN).unwrap();
+    assert_eq!(sketch.block_mask(), 0);
+    assert!(FrequencyCountSketch::with_table_len(frequency_count_sketch::MIN_TABLE_LEN >> 1).is_err());
+}
+
+#[test]
+fn test_increment_by_advances_pending_sweep() {
+    let mut by = FrequencyCountSketch::new(256);
+    by.set_incremental_reset(true);
+    by.increment_by("hot", 15);
+    let mut i = 0u32;
+    while !by.increment_checked(i) {
+        i += 1;
+    }
+    assert!(by.reset_in_progress());
+    assert_eq!(by.frequency("hot"), 15);
+
+    let mut repeated = by.clone();
+    by.increment_by("hot", 40);
+    for _ in 0..40 {
+        repeated.increment("hot");
+    }
+    assert!(!by.reset_in_progress());
+    assert!(!repeated.reset_in_progress());
+    assert_eq!(by, repeated);
+}
+
+#[test]
+fn test_incremental_reset() {
+    let mut incremental = FrequencyCountSketch::new(256);
+    incremental.set_incremental_reset(true);
+    let mut eager = FrequencyCountSketch::new(256);
+
+    let mut i = 0;
+    loop {
+        let started = incremental.increment_checked(i);
+        assert_eq!(eager.increment_checked(i), started);
+        i += 1;
+        if started {
+            break;
+        }
+    }
+    assert!(incremental.reset_in_progress());
+    assert!(!eager.reset_in_progress());
+
+    // Probe increments only touch the probe's counters while the sweep ages the table
+    let mut increments = 0;
+    while incremental.reset_in_progress() {
+        incremental.increment("probe");
+        eager.increment("probe");
+        increments += 1;
+    }
+    assert_eq!(increments, incremental.get_table_len() / 8);
+
+    let (words, nibbles) = incremental.locate("probe");
+    let probe: Vec<usize> = (0..4).map(|j| words[j] * 16 + nibbles[j]).collect();
+    let halved_once = incremental.decode_counters();
+    let expected = eager.decode_counters();
+    for (c, (a, b)) in halved_once.iter().zip(expected.iter()).enumerate() {
+        if !probe.contains(&c) {
+            assert_eq!(a, b, "counter {}", c);
+        }
+    }
+    assert!(incremental.estimated_cardinality() < incremental.sample_size() / 2 + increments);
+}
+
+fn record_accesses<T: FrequencyEstimator>(estimator: &mut T, keys: &[&str]) -> u8 {
+    for key in keys {
+        estimator.increment(key);
+    }
+    estimator.frequency(keys[0])
+}
+
+#[test]
+fn test_frequency_estimator() {
+    let keys = ["a", "b", "a", "a"];
+    assert_eq!(record_accesses(&mut FrequencyCountSketch::new(64), &keys), 3);
+    assert_eq!(record_accesses(&mut FrequencyCountSketch2::new(64), &keys), 3);
+}
+
+#[test]
+fn test_hashed() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    let mut other = FrequencyCountSketch::new(1024);
+    let hash_code = frequency_count_sketch::default_hash_code("key");
+    sketch.increment("key");
+    sketch.increment_hashed(hash_code);
+    other.increment_hashed(hash_code);
+
+    assert_eq!(sketch.frequency_hashed(hash_code), sketch.frequency("key"));
+    assert_eq!(sketch.frequency("key"), 2);
+    assert_eq!(other.frequency("key"), 1);
+}
+
+#[test]
+fn test_from_decoded() {
+    let mut sketch = FrequencyCountSketch::new(100);
+    for i in 0..50 {
+        sketch.increment_by(i, i % 7);
+    }
+    let counters = sketch.decode_counters();
+    let restored = FrequencyCountSketch::from_decoded(&counters, 100).unwrap();
+    assert_eq!(restored.decode_counters(), counters);
+    for i in 0..50 {
+        assert_eq!(restored.frequency(i), sketch.frequency(i));
+    }
+
+    assert_eq!(
+        FrequencyCountSketch::from_decoded(&counters, 1000).unwrap_err(),
+        SketchError::InvalidCounterCount { expected: 1024 * 16, actual: 128 * 16 }
+    );
+    let mut invalid = counters.clone();
+    invalid[5] = 16;
+    assert_eq!(
+        FrequencyCountSketch::from_decoded(&invalid, 100).unwrap_err(),
+        SketchError::CounterOutOfRange { index: 5, value: 16 }
+    );
+}
+
+#[test]
+fn test_two_bit_sketch_aging() {
+    let mut sketch = FrequencyCountSketch2::new(1024);
+    for (key, count) in [(1, 1), (2, 2), (3, 3), (4, 10)] {
+        for _ in 0..count {
+            sketch.increment(key);
+        }
+    }
+    sketch.reset();
+    assert_eq!(sketch.frequency(1), 0);
+    assert_eq!(sketch.frequency(2), 1);
+    assert_eq!(sketch.frequency(3), 1);
+    assert_eq!(sketch.frequency(4), 1);
+
+    // Reaching the sample size ages the sketch on its own
+    let mut sketch = FrequencyCountSketch2::new(1);
+    for _ in 0..3 {
+        sketch.increment("hot");
+    }
+    let mut i = 0;
+    while sketch.frequency("hot") == 3 {
+        sketch.increment(i);
+        i += 1;
+    }
+    assert_eq!(sketch.frequency("hot"), 1);
+    assert!(i < 10);
+}
+
+#[test]
+fn test_frequency_upper() {
+    let mut isolated = FrequencyCountSketch::new(4096);
+    isolated.increment_by(1, 3);
+    assert_eq!(isolated.frequency(1), 3);
+    assert_eq!(isolated.frequency_upper(1), 3);
+
+    let mut crowded = FrequencyCountSketch::new(8);
+    for i in 100..108 {
+        crowded.increment_by(i, 8);
+    }
+    crowded.increment(1);
+    assert!(crowded.frequency_upper(1) > crowded.frequency(1));
+}
+
+#[test]
+fn test_counters_for() {
+    let mut sketch = FrequencyCountSketch::new(4096);
+    assert_eq!(sketch.counters_for("key"), [0; 4]);
+    sketch.increment_by("key", 6);
+    assert_eq!(sketch.counters_for("key"), [6; 4]);
+    assert_eq!(sketch.frequency("key"), 6);
+}
+
+#[test]
+fn test_admit() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_by("hot", 10);
+    assert!(!sketch.admit("cold", "hot"));
+    assert_eq!(sketch.frequency("cold"), 1);
+
+    sketch.increment_by("cold", 4);
+    assert!(sketch.admit("hot", "cold"));
+    assert!(!sketch.admit("cold", "cold"));
+}
+
+#[test]
+fn test_byte_slice_round_trip() {
+    let mut sketch = FrequencyCountSketch::new(100);
+    for i in 0..50 {
+        sketch.increment_by(i, i % 9);
+    }
+    let bytes = sketch.as_byte_slice();
+    assert_eq!(bytes.len(), sketch.memory_usage_bytes());
+
+    let restored = FrequencyCountSketch::from_byte_slice(bytes, 100).unwrap();
+    assert_eq!(restored.decode_counters(), sketch.decode_counters());
+    for i in 0..50 {
+        assert_eq!(restored.frequency(i), sketch.frequency(i));
+    }
+
+    assert_eq!(
+        FrequencyCountSketch::from_byte_slice(&bytes[1..], 100).unwrap_err(),
+        SketchError::InvalidByteLength { expected: 1024, actual: 1023 }
+    );
+}
+
+#[test]
+fn test_reset_weighted() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_by("hot", 15);
+    sketch.increment_by("cold", 4);
+
+    let mut uniform = sketch.clone();
+    uniform.reset();
+    assert_eq!(uniform.frequency("hot"), 7);
+    assert_eq!(uniform.frequency("cold"), 2);
+
+    sketch.reset_weighted(4);
+    assert_eq!(sketch.frequency("hot"), 9);
+    assert_eq!(sketch.frequency("cold"), 2);
+    assert!(sketch.frequency("hot") as f64 / 15.0 > sketch.frequency("cold") as f64 / 4.0);
+    assert_eq!(sketch.estimated_cardinality(), 11);
+}
+
+#[test]
+fn test_collision_estimate() {
+    let keys: Vec<u32> = (0..1000).collect();
+    let crowded = FrequencyCountSketch::collision_estimate(8, keys.iter());
+    let roomy = FrequencyCountSketch::collision_estimate(1 << 16, keys.iter());
+    assert!(crowded > 0.99, "crowded {}", crowded);
+    assert!(roomy < 0.05, "roomy {}", roomy);
+    assert_eq!(FrequencyCountSketch::collision_estimate(8, Vec::<u32>::new()), 0.0);
+    assert_eq!(FrequencyCountSketch::collision_estimate(8, [1, 1, 1]), 0.0);
+
+    let sketch = FrequencyCountSketch::new(64);
+    let (words, nibbles) = sketch.locate(7);
+    let positions = sketch.counter_positions(7);
+    for i in 0..4 {
+        assert_eq!(positions[i], words[i] * 16 + nibbles[i]);
+    }
+}
+
+#[test]
+fn test_sample_size_does_not_wrap() {
+    let maximum = i32::MAX as usize >> 1;
+    let sample_size = frequency_count_sketch::sample_size_for(maximum);
+    assert!(sample_size >= maximum);
+    if cfg!(target_pointer_width = "64") {
+        assert_eq!(sample_size as u64, 10 * maximum as u64);
+    } else {
+        assert_eq!(sample_size, usize::MAX);
+    }
+    assert_eq!(frequency_count_sketch::sample_size_for(0), 10);
+    assert_eq!(frequency_count_sketch::sample_size_for(1000), 10_000);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_maybe_reset_after() {
+    let period = Duration::from_secs(60);
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_by("key", 12);
+
+    assert!(!sketch.maybe_reset_after(Duration::from_secs(25), period));
+    assert!(!sketch.maybe_reset_after(Duration::from_secs(25), period));
+    assert_eq!(sketch.frequency("key"), 12);
+
+    // 75 seconds accumulated, one reset with 15 seconds left over
+    assert!(sketch.maybe_reset_after(Duration::from_secs(25), period));
+    assert_eq!(sketch.frequency("key"), 6);
+    assert!(!sketch.maybe_reset_after(Duration::from_secs(44), period));
+    assert!(sketch.maybe_reset_after(Duration::from_secs(1), period));
+    assert_eq!(sketch.frequency("key"), 3);
+
+    // Two periods at once age twice
+    sketch.increment_by("key", 9);
+    assert!(sketch.maybe_reset_after(Duration::from_secs(120), period));
+    assert_eq!(sketch.frequency("key"), 3);
+
+    // A long pause clears a gently decaying sketch too, which takes more than four resets
+    let mut slow = FrequencyCountSketch::new_with_decay(1024, 0.9).unwrap();
+    slow.increment_by("key", 15);
+    assert!(slow.maybe_reset_after(Duration::from_secs(3600), period));
+    assert_eq!(slow.frequency("key"), 0);
+    assert!(slow.reset_count() > 4);
+
+    // A pause of billions of periods runs the capped resets without stepping through every period
+    let mut paused = FrequencyCountSketch::new(1024);
+    paused.increment_by("key", 15);
+    assert!(paused.maybe_reset_after(Duration::from_secs(10), Duration::from_nanos(1)));
+    assert_eq!(paused.reset_count(), 4);
+    assert_eq!(paused.frequency("key"), 0);
+    assert!(paused.maybe_reset_after(Duration::MAX, Duration::from_nanos(7)));
+    assert_eq!(paused.reset_count(), 8);
+
+    // The leftover below one period is still carried over
+    let odd = Duration::from_nanos(7);
+    let mut carried = FrequencyCountSketch::new(1024);
+    assert!(carried.maybe_reset_after(Duration::from_nanos(7 * 1_000_000_000 + 5), odd));
+    assert!(!carried.maybe_reset_after(Duration::from_nanos(1), odd));
+    assert!(carried.maybe_reset_after(Duration::from_nanos(1), odd));
+    assert_eq!(carried.reset_count(), 5);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_increment_at_time() {
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.set_reset_period(Some(Duration::from_secs(60)));
+    for secs in 0..12 {
+        sketch.increment_at_time("key", at(secs));
+    }
+    assert_eq!(sketch.frequency("key"), 12);
+    assert_eq!(sketch.reset_count(), 0);
+
+    // Crossing the period ages the sketch before counting the access
+    sketch.increment_at_time("key", at(61));
+    assert_eq!(sketch.reset_count(), 1);
+    assert_eq!(sketch.frequency("key"), 7);
+    sketch.increment_at_time("key", at(100));
+    assert_eq!(sketch.reset_count(), 1);
+    sketch.increment_at_time("key", at(125));
+    assert_eq!(sketch.reset_count(), 2);
+    assert_eq!(sketch.frequency("key"), 5);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_increment_from_reader() {
+    let log = "alpha\nbeta\nalpha\r\ngamma\nalpha\n";
+    let mut sketch = FrequencyCountSketch::new(1024);
+    assert_eq!(sketch.increment_from_reader(std::io::Cursor::new(log)).unwrap(), 5);
+    assert_eq!(sketch.frequency("alpha"), 3);
+    assert_eq!(sketch.frequency("beta"), 1);
+    assert_eq!(sketch.frequency(String::from("gamma")), 1);
+    assert_eq!(sketch.increment_from_reader(std::io::Cursor::new("")).unwrap(), 0);
+
+    let invalid: &[u8] = b"alpha\n\xff\n";
+    assert!(sketch.increment_from_reader(invalid).is_err());
+    assert_eq!(sketch.frequency("alpha"), 4);
+}
+
+#[test]
+fn test_new_with_decay() {
+    let mut halving = FrequencyCountSketch::new(256);
+    let mut decaying = FrequencyCountSketch::new_with_decay(256, 0.5).unwrap();
+    for i in 0..5000 {
+        halving.increment(i % 300);
+        decaying.increment(i % 300);
+        assert_eq!(decaying, halving);
+    }
+
+    let mut smooth = FrequencyCountSketch::new_with_decay(1024, 0.75).unwrap();
+    smooth.increment_by("key", 12);
+    smooth.increment_by("rare", 1);
+    smooth.reset();
+    assert_eq!(smooth.frequency("key"), 9);
+    assert_eq!(smooth.frequency("rare"), 0);
+    assert_eq!(smooth.estimated_cardinality(), 13 * 3 / 4);
+
+    for decay in [0.0, 1.0, -0.5, 2.0, f32::NAN] {
+        assert!(FrequencyCountSketch::new_with_decay(64, decay).is_err());
+    }
+}
+
+#[test]
+fn test_sparse_round_trip() {
+    let mut sketch = FrequencyCountSketch::new(4096);
+    for i in 0..20 {
+        sketch.increment_by(i, 1 + i % 5);
+    }
+    let sparse = sketch.to_sparse();
+    assert!(!sparse.is_empty() && sparse.len() <= 80);
+    assert!(sparse.len() < sketch.get_table_len() / 10);
+
+    let mut restored = FrequencyCountSketch::new(4096);
+    restored.apply_sparse(&sparse).unwrap();
+    assert_eq!(restored.decode_counters(), sketch.decode_counters());
+    for i in 0..20 {
+        assert_eq!(restored.frequency(i), sketch.frequency(i));
+    }
+
+    assert_eq!(
+        restored.apply_sparse(&[(0, 1), (4096, 1)]).unwrap_err(),
+        SketchError::WordIndexOutOfRange(4096)
+    );
+    assert_eq!(restored.decode_counters(), sketch.decode_counters());
+}
+
+#[test]
+fn test_raw_parts_round_trip() {
+    let mut sketch = FrequencyCountSketch::new(100);
+    for i in 0..60 {
+        sketch.increment_by(i, i % 4);
+    }
+    let table = sketch.raw_table().to_vec();
+    let restored = FrequencyCountSketch::from_raw_parts(&table, 100, sketch.estimated_cardinality()).unwrap();
+    assert_eq!(restored, sketch);
+    for i in 0..60 {
+        assert_eq!(restored.frequency(i), sketch.frequency(i));
+    }
+
+    for len in [0usize, 4, 12, 100] {
+        assert_eq!(
+            FrequencyCountSketch::from_raw_parts(&vec![0; len], 100, 0).unwrap_err(),
+            SketchError::InvalidTableLength(len)
+        );
+    }
+}
+
+#[test]
+fn test_check_invariants() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    assert_eq!(sketch.check_invariants(), Ok(()));
+    for i in 0..2_000 {
+        sketch.increment(i % 97);
+        assert_eq!(sketch.check_invariants(), Ok(()));
+    }
+    sketch.decay(3, 4);
+    assert_eq!(sketch.check_invariants(), Ok(()));
+    sketch.reset();
+    assert_eq!(sketch.check_invariants(), Ok(()));
+
+    let mut incremental = FrequencyCountSketch::new(64);
+    incremental.set_incremental_reset(true);
+    for i in 0..2_000 {
+        incremental.increment(i);
+        assert_eq!(incremental.check_invariants(), Ok(()));
+    }
+}
+
+#[test]
+fn test_check_invariants_detects_corruption() {
+    let sketch = FrequencyCountSketch::new(64);
+    let sample_size = sketch.sample_size();
+    let corrupted = FrequencyCountSketch::from_raw_parts(sketch.raw_table(), 64, sample_size + 1).unwrap();
+    assert!(matches!(corrupted.check_invariants(), Err(SketchError::CorruptState(_))));
+}
+
+#[test]
+fn test_access() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    assert_eq!(sketch.access("key"), 1);
+    assert_eq!(sketch.access("key"), sketch.frequency("key"));
+    assert_eq!(sketch.frequency("key"), 2);
+}
+
+#[cfg(feature = "distinct-count")]
+#[test]
+fn test_estimated_distinct() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    assert_eq!(sketch.estimated_distinct(), 0);
+    for n in [100u64, 10_000] {
+        let mut sketch = FrequencyCountSketch::new(512);
+        for i in 0..n {
+            sketch.increment(i);
+            sketch.increment(i);
+        }
+        let estimate = sketch.estimated_distinct() as f64;
+        assert!((estimate - n as f64).abs() < 0.2 * n as f64, "estimated {} for {}", estimate, n);
+    }
+    sketch.increment_by("key", 5);
+    assert_eq!(sketch.estimated_distinct(), 1);
+
+    // The distinct counter reads the top bits of the full 64-bit spread hash, whatever the width of
+    // usize, and agrees with the block hash that places the counters
+    let mut top_bits = std::collections::HashSet::new();
+    for i in 0..10_000u64 {
+        let hash_code = frequency_count_sketch::default_hash_code(i);
+        let spread = frequency_count_sketch::spread64(hash_code);
+        assert_eq!(spread as usize, frequency_count_sketch::spread(hash_code));
+        top_bits.insert(spread >> 56);
+    }
+    assert_eq!(top_bits.len(), 256);
+    let mut batched = FrequencyCountSketch::new(512);
+    batched.increment_batch_with_aging(0..1000u64);
+    for i in 0..1000u64 {
+        batched.touch(i);
+        batched.increment_unchecked(i);
+    }
+    let estimate = batched.estimated_distinct() as f64;
+    assert!((estimate - 1000.0).abs() < 200.0, "estimated {} for 1000", estimate);
+}
+
+#[test]
+fn test_byte_representation_keys() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_key("key");
+    sketch.increment_key(String::from("key"));
+    let bytes: Vec<u8> = b"key".to_vec();
+    sketch.increment_key(bytes);
+    sketch.increment_key("other");
+    assert_eq!(sketch.frequency_key("key"), 3);
+    assert_eq!(sketch.frequency_key(String::from("key")), 3);
+    assert_eq!(sketch.frequency_key(vec![b'k', b'e', b'y']), 3);
+    assert_eq!(sketch.frequency_bytes(b"key"), 3);
+    assert_eq!(sketch.frequency_key("other"), 1);
+}
+
+#[test]
+fn test_sliding_frequency_sketch() {
+    let mut sketch = SlidingFrequencySketch::new(512);
+    for _ in 0..6 {
+        sketch.increment("hot");
+    }
+    assert_eq!(sketch.frequency("hot"), 6);
+
+    sketch.rotate();
+    sketch.increment("new");
+    assert_eq!(sketch.current().frequency("hot"), 0);
+    assert_eq!(sketch.frequency("hot"), 3);
+    assert_eq!(sketch.frequency("new"), 1);
+
+    sketch.rotate();
+    assert_eq!(sketch.frequency("hot"), 0);
+    assert_eq!(sketch.frequency("new"), 0);
+}
+
+#[test]
+fn test_increment_does_not_carry_into_neighbor() {
+    let template = FrequencyCountSketch::new(64);
+    let (words, nibbles) = template.locate("key");
+    let mut table = template.raw_table().to_vec();
+    for (&word, &nibble) in words.iter().zip(nibbles.iter()) {
+        table[word] |= 14u64 << (nibble << 2) | 15u64 << ((nibble ^ 1) << 2);
+    }
+    let mut sketch = FrequencyCountSketch::from_raw_parts(&table, 64, 0).unwrap();
+    assert_eq!(sketch.frequency("key"), 14);
+
+    for _ in 0..2 {
+        sketch.increment("key");
+        assert_eq!(sketch.frequency("key"), 15);
+        for (&word, &nibble) in words.iter().zip(nibbles.iter()) {
+            let w = sketch.raw_table()[word];
+            assert_eq!(w >> (nibble << 2) & 0xf, 15);
+            assert_eq!(w >> ((nibble ^ 1) << 2) & 0xf, 15);
+            assert_eq!(w & !(0xffu64 << ((nibble & !1) << 2)), 0);
+        }
+    }
+}
+
+#[test]
+fn test_aging_disabled() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.set_aging_enabled(false);
+    let sample_size = sketch.sample_size();
+    sketch.increment_by("hot", 10);
+    for i in 0..sample_size * 2 {
+        sketch.increment(i);
+    }
+    assert_eq!(sketch.estimated_cardinality(), sample_size);
+    assert!(sketch.frequency("hot") >= 10);
+    assert_eq!(sketch.check_invariants(), Ok(()));
+
+    sketch.set_aging_enabled(true);
+    sketch.increment("cold");
+    assert!(sketch.estimated_cardinality() < sample_size);
+    assert!(sketch.frequency("hot") <= 7);
+}
+
+#[test]
+fn test_is_heavy_hitter() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    sketch.increment_by("key", 5);
+    assert_eq!(sketch.frequency("key"), 5);
+    assert!(sketch.is_heavy_hitter("key", 4));
+    assert!(sketch.is_heavy_hitter("key", 5));
+    assert!(!sketch.is_heavy_hitter("key", 6));
+    assert!(sketch.is_heavy_hitter("absent", 0));
+    assert!(!sketch.is_heavy_hitter("absent", 1));
+}
+
+#[test]
+fn test_load_zipfian() {
+    let stream: Vec<u64> = workload::zipfian_stream(1_000, 20_000, 1.0).collect();
+    assert_eq!(stream.len(), 20_000);
+    assert!(stream.iter().all(|&k| k < 1_000));
+    assert_eq!(stream, workload::zipfian_stream(1_000, 20_000, 1.0).collect::<Vec<u64>>());
+
+    let sketch = workload::load_zipfian(1_000, 20_000, 1.0);
+    assert!(sketch.frequency(0u64) > sketch.frequency(999u64));
+    assert!(sketch.frequency(0u64) >= 7);
+}
+
+#[test]
+fn test_rank() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    let keys = ["cold", "hot", "warm", "absent", "tepid"];
+    sketch.increment_by("cold", 1);
+    sketch.increment_by("hot", 12);
+    sketch.increment_by("warm", 6);
+    sketch.increment_by("tepid", 3);
+    let ranked = sketch.rank(&keys);
+    assert_eq!(ranked, vec![("hot", 12), ("warm", 6), ("tepid", 3), ("cold", 1), ("absent", 0)]);
+    assert!(sketch.rank::<&str>(&[]).is_empty());
+
+    // Equal estimates keep their input order in either direction
+    sketch.increment_by("peer", 6);
+    let ties = sketch.rank(&["peer", "tepid", "warm"]);
+    assert_eq!(ties, vec![("peer", 6), ("warm", 6), ("tepid", 3)]);
+    let ties = sketch.rank(&["warm", "peer"]);
+    assert_eq!(ties, vec![("warm", 6), ("peer", 6)]);
+}
+
+#[test]
+fn test_new_independent() {
+    let mut sketch = FrequencyCountSketch::new_independent(1024);
+    sketch.increment_by("key", 5);
+    assert_eq!(sketch.frequency("key"), 5);
+    assert_eq!(sketch.counters_for("key"), [5; 4]);
+
+    // Keys sharing the target's block in the single-hash layout inflate its estimate there, but are
+    // spread over the whole table when every depth has its own hash
+    let mut single = FrequencyCountSketch::new(1024);
+    let mut independent = FrequencyCountSketch::new_independent(1024);
+    let target_block = single.locate("target").0[0] >> 3;
+    let mut attackers = 0;
+    for i in 0u64.. {
+        if attackers == 400 {
+            break;
+        }
+        if single.locate(i).0[0] >> 3 == target_block {
+            single.increment(i);
+            independent.increment(i);
+            attackers += 1;
+        }
+    }
+    assert!(single.frequency("target") >= 3);
+    assert!(independent.frequency("target") < single.frequency("target"));
+    assert_eq!(independent.frequency("target"), 0);
+}
+
+#[test]
+fn test_compare() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    sketch.increment_by("hot", 4);
+    sketch.increment_by("warm", 2);
+    sketch.increment_by(7u64, 2);
+    assert_eq!(sketch.compare("hot", "warm"), Ordering::Greater);
+    assert_eq!(sketch.compare("warm", "hot"), Ordering::Less);
+    assert_eq!(sketch.compare("warm", 7u64), Ordering::Equal);
+}
+
+#[test]
+fn test_table_is_cache_line_aligned() {
+    for table_len in [8, 64, 1024] {
+        let sketch = FrequencyCountSketch::with_table_len(table_len).unwrap();
+        assert_eq!(sketch.raw_table().as_ptr() as usize % 64, 0);
+        assert_eq!(sketch.raw_table().len(), table_len);
+    }
+    let mut sketch = FrequencyCountSketch::new(4096);
+    sketch.compact(100);
+    assert_eq!(sketch.raw_table().as_ptr() as usize % 64, 0);
+    let restored = FrequencyCountSketch::from_raw_parts(&[1; 16], 16, 0).unwrap();
+    assert_eq!(restored.raw_table().as_ptr() as usize % 64, 0);
+    assert_eq!(restored.raw_table(), &[1; 16][..]);
+}
+
+#[test]
+fn test_little_endian_bytes() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    for i in 0..200 {
+        sketch.increment(i % 50);
+    }
+    let bytes = sketch.to_bytes();
+    assert_eq!(bytes.len(), sketch.memory_usage_bytes());
+
+    // A big-endian host holds each word in the opposite byte order, which the encoding undoes
+    let swapped: Vec<u8> = sketch.raw_table().iter().flat_map(|w| w.swap_bytes().to_be_bytes()).collect();
+    let expected: Vec<u8> = sketch.raw_table().iter().flat_map(|w| w.to_le_bytes()).collect();
+    assert_eq!(bytes, expected);
+    assert_eq!(bytes, swapped);
+    assert_eq!(bytes[0], (sketch.raw_table()[0] & 0xff) as u8);
+    if cfg!(target_endian = "little") {
+        assert_eq!(bytes, sketch.as_byte_slice());
+    }
+
+    let restored = FrequencyCountSketch::from_bytes(&bytes, 64).unwrap();
+    assert_eq!(restored.raw_table(), sketch.raw_table());
+    assert_eq!(
+        FrequencyCountSketch::from_bytes(&bytes[1..], 64).unwrap_err(),
+        SketchError::InvalidByteLength { expected: bytes.len(), actual: bytes.len() - 1 }
+    );
+}
+
+#[test]
+fn test_peek() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.increment_by("key", 3);
+    let table = sketch.raw_table().to_vec();
+    for _ in 0..100 {
+        assert_eq!(sketch.peek("key"), 3);
+    }
+    assert_eq!(sketch.estimated_cardinality(), 3);
+    assert_eq!(sketch.raw_table(), &table[..]);
+}
+
+#[cfg(feature = "prefetch")]
+#[test]
+fn test_prefetch_does_not_affect_counts() {
+    for mut sketch in [FrequencyCountSketch::new(256), FrequencyCountSketch::new_independent(256)] {
+        for i in 0..100u32 {
+            sketch.increment_by(i, i % 8);
+        }
+        for i in 0..100u32 {
+            assert!(sketch.frequency(i) >= (i % 8) as u8);
+        }
+        assert_eq!(sketch.frequency("absent"), 0);
+    }
+}
+
+#[cfg(feature = "simd")]
+#[test]
+fn test_simd_frequency_matches_scalar() {
+    for mut sketch in [FrequencyCountSketch::new(128), FrequencyCountSketch::new_independent(128)] {
+        for i in 0..1_000u32 {
+            sketch.increment_by(i % 300, i % 5);
+        }
+        for i in 0..2_000u32 {
+            assert_eq!(sketch.frequency(i), *sketch.counters_for(i).iter().min().unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_with_block_words() {
+    let default = FrequencyCountSketch::new(1024);
+    let eight = FrequencyCountSketch::with_block_words(1024, 8).unwrap();
+    assert_eq!(eight.block_words(), 8);
+    assert_eq!(eight.block_mask(), default.block_mask());
+    for i in 0..100 {
+        assert_eq!(eight.locate(i), default.locate(i));
+    }
+
+    let mut sixteen = FrequencyCountSketch::with_block_words(1024, 16).unwrap();
+    assert_eq!(sixteen.block_words(), 16);
+    assert_eq!(sixteen.block_mask(), 1024 / 16 - 1);
+    for i in 0..100 {
+        let (words, _) = sixteen.locate(i);
+        let block = words[0] >> 4;
+        for (row, &word) in words.iter().enumerate() {
+            assert_eq!(word >> 4, block);
+            assert_eq!((word & 15) >> 2, row);
+        }
+        sixteen.increment_by(i, 3);
+    }
+    for i in 0..100 {
+        assert!(sixteen.frequency(i) >= 3);
+    }
+    assert_eq!(sixteen.check_invariants(), Ok(()));
+
+    let small = FrequencyCountSketch::with_block_words(4, 32).unwrap();
+    assert_eq!(small.get_table_len(), 32);
+    assert_eq!(small.block_mask(), 0);
+    for words in [0, 4, 12, 64] {
+        assert_eq!(
+            FrequencyCountSketch::with_block_words(1024, words).unwrap_err(),
+            SketchError::InvalidBlockWords(words)
+        );
+    }
+}
+
+#[test]
+fn test_filter_heavy() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    sketch.increment_by("hot", 9);
+    sketch.increment_by("warm", 4);
+    sketch.increment_by("cold", 1);
+    sketch.increment_by("blazing", 15);
+    let candidates = ["cold", "hot", "absent", "warm", "blazing"];
+    assert_eq!(sketch.filter_heavy(&candidates, 4), vec!["hot", "warm", "blazing"]);
+    assert_eq!(sketch.filter_heavy(&candidates, 9), vec!["hot", "blazing"]);
+    assert_eq!(sketch.filter_heavy(&candidates, 10), vec!["blazing"]);
+    assert_eq!(sketch.filter_heavy(&candidates, 0).len(), candidates.len());
+}
+
+#[test]
+fn test_min_frequency() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    sketch.increment_by("hot", 9);
+    sketch.increment_by("warm", 4);
+    sketch.increment_by("cool", 2);
+    assert_eq!(sketch.min_frequency(["hot", "warm", "cool", "absent"]), Some(2));
+    assert_eq!(sketch.min_frequency(["hot", "warm"]), Some(4));
+    assert_eq!(sketch.min_frequency(["absent"]), None);
+    assert_eq!(sketch.min_frequency(Vec::<&str>::new()), None);
+}
+
+#[test]
+fn test_static_frequency_sketch() {
+    let mut sketch = StaticFrequencySketch::<1024>::new();
+    let mut heap = FrequencyCountSketch::new(1024);
+    assert_eq!(sketch.get_table_len(), heap.get_table_len());
+    for i in 0..20_000u32 {
+        sketch.increment(i % 3_000);
+        heap.increment(i % 3_000);
+    }
+    for i in 0..3_000u32 {
+        assert_eq!(sketch.frequency(i), heap.frequency(i));
+    }
+
+    sketch.reset();
+    heap.reset();
+    for i in 0..3_000u32 {
+        assert_eq!(sketch.frequency(i), heap.frequency(i));
+    }
+    assert_eq!(StaticFrequencySketch::<8>::default().frequency("absent"), 0);
+
+    const EMPTY: StaticFrequencySketch<64> = StaticFrequencySketch::new();
+    let mut on_stack = EMPTY;
+    for _ in 0..3 {
+        on_stack.increment("key");
+    }
+    assert_eq!(on_stack.frequency("key"), 3);
+    assert_eq!(EMPTY.frequency("key"), 0);
+}
+
+#[test]
+fn test_occupancy() {
+    for incremental in [false, true] {
+        let mut sketch = FrequencyCountSketch::new_adaptive(64);
+        sketch.set_incremental_reset(incremental);
+        assert_eq!(sketch.occupancy(), 0.0);
+        let mut resets = 0;
+        for i in 0..5_000u32 {
+            let before = sketch.occupancy();
+            let reset = sketch.increment_checked(i % 700);
+            assert!((0.0..=1.0).contains(&sketch.occupancy()));
+            assert_eq!(sketch.check_invariants(), Ok(()));
+            if reset {
+                resets += 1;
+                if !incremental {
+                    assert!(sketch.occupancy() < before);
+                }
+            }
+        }
+        assert!(resets > 1);
+    }
+}
+
+#[test]
+fn test_block_hash_entry_points() {
+    for mut sketch in [FrequencyCountSketch::new(512), FrequencyCountSketch::new_independent(512)] {
+        let mut reference = sketch.clone();
+        for i in 0..300u32 {
+            let block_hash = frequency_count_sketch::spread(frequency_count_sketch::default_hash_code(i % 97));
+            sketch.increment_from_block_hash(block_hash);
+            reference.increment(i % 97);
+        }
+        assert_eq!(sketch, reference);
+        for i in 0..200u32 {
+            let block_hash = frequency_count_sketch::spread(frequency_count_sketch::default_hash_code(i));
+            assert_eq!(sketch.frequency_from_block_hash(block_hash), sketch.frequency(i));
+        }
+    }
+}
+
+#[test]
+fn test_on_reset_fires_once_at_sample_size() {
+    let resets = Arc::new(Mutex::new(0u32));
+    let mut sketch = FrequencyCountSketch::new(64);
+    let counter = resets.clone();
+    sketch.on_reset(move |_| *counter.lock().unwrap() += 1);
+
+    let mut i = 0u32;
+    loop {
+        let before = sketch.estimated_cardinality();
+        let reset = sketch.increment_checked(i);
+        i += 1;
+        if reset {
+            assert_eq!(before, sketch.sample_size() - 1);
+            break;
+        }
+        assert_eq!(*resets.lock().unwrap(), 0);
+    }
+    assert_eq!(*resets.lock().unwrap(), 1);
+}
+
+#[test]
+fn test_depth_frequency_sketch() {
+    let mut default: DepthFrequencySketch = DepthFrequencySketch::new(512);
+    let mut reference = FrequencyCountSketch::new(512);
+    assert_eq!(default.depth(), 4);
+    for i in 0..3_000u32 {
+        default.increment(i % 700);
+        reference.increment(i % 700);
+    }
+    for i in 0..700u32 {
+        assert_eq!(default.frequency(i), reference.frequency(i));
+    }
+
+    let mut shallow = DepthFrequencySketch::<3>::new(512);
+    let mut deep = DepthFrequencySketch::<8>::new(512);
+    assert_eq!((shallow.depth(), deep.depth()), (3, 8));
+    for i in 0..400u32 {
+        for _ in 0..i % 6 {
+            shallow.increment(i);
+            deep.increment(i);
+        }
+    }
+    for i in 0..400u32 {
+        assert!(shallow.frequency(i) >= (i % 6) as u8);
+        assert!(deep.frequency(i) >= (i % 6) as u8);
+    }
+    assert_eq!(deep.frequency("absent"), 0);
+
+    shallow.reset();
+    deep.reset();
+    for i in 0..400u32 {
+        assert!(shallow.frequency(i) >= (i % 6 / 2) as u8);
+        assert!(deep.frequency(i) >= (i % 6 / 2) as u8);
+    }
+}
+
+#[test]
+fn test_overcount_estimate() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_by("isolated", 3);
+    assert_eq!(sketch.overcount_estimate("isolated"), 0);
+
+    // Keys sharing the target's block collide with some of its counters
+    let target_block = sketch.locate("target").0[0] >> 3;
+    sketch.increment_by("target", 2);
+    let mut attackers = 0;
+    for i in 0u64.. {
+        if attackers == 300 {
+            break;
+        }
+        if sketch.locate(i).0[0] >> 3 == target_block {
+            sketch.increment(i);
+            attackers += 1;
+        }
+    }
+    assert!(sketch.overcount_estimate("target") > sketch.overcount_estimate("isolated"));
+}
+
+#[cfg(feature = "cms-adapter")]
+#[test]
+fn test_from_count_min_sketch() {
+    let mut source = count_min_sketch::CountMinSketch64::<u64>::new(1_000, 0.99, 2.0).unwrap();
+    for key in 0..100u64 {
+        for _ in 0..key % 20 {
+            source.increment(&key);
+        }
+    }
+    let keys: Vec<u64> = (0..100).collect();
+    let sketch = FrequencyCountSketch::from_count_min_sketch(1_000, &source, &keys);
+    for key in 0..100u64 {
+        let expected = source.estimate(&key).min(15) as u8;
+        assert!(sketch.frequency(key) >= expected);
+    }
+    assert!(sketch.frequency(19u64) > sketch.frequency(1u64));
+    assert_eq!(sketch.frequency(15u64), 15);
+    assert_eq!(sketch.frequency(1_000u64), 0);
+}
+
+#[test]
+fn test_frequencies_close() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    let mut other = FrequencyCountSketch::new(64);
+    for i in 0..50u32 {
+        sketch.increment_by(i, i % 8);
+        other.increment_by(i, i % 3);
+    }
+    let clone = sketch.clone();
+    for i in 0..50u32 {
+        assert!(sketch.frequencies_close(&clone, i, 0));
+    }
+
+    let merged_counters: Vec<u8> = sketch
+        .decode_counters()
+        .iter()
+        .zip(other.decode_counters())
+        .map(|(&a, b)| (a + b).min(15))
+        .collect();
+    let merged = FrequencyCountSketch::from_decoded(&merged_counters, 64).unwrap();
+    assert!((0..50u32).any(|i| !sketch.frequencies_close(&merged, i, 0)));
+    // The merged estimates exceed the originals by about the other sketch's counts, all below 3
+    for i in 0..50u32 {
+        assert!(sketch.frequencies_close(&merged, i, 2));
+    }
+}
+
+#[test]
+fn test_drain() {
+    let mut sketch = FrequencyCountSketch::new(256);
+    sketch.set_aggressive_aging(true);
+    for i in 0..100u32 {
+        sketch.increment_by(i, i % 6);
+    }
+    let before: Vec<u8> = (0..100u32).map(|i| sketch.frequency(i)).collect();
+    let cardinality = sketch.estimated_cardinality();
+
+    let snapshot = sketch.drain();
+    assert_eq!((0..100u32).map(|i| snapshot.frequency(i)).collect::<Vec<u8>>(), before);
+    assert_eq!(snapshot.estimated_cardinality(), cardinality);
+    for i in 0..100u32 {
+        assert_eq!(sketch.frequency(i), 0);
+    }
+    assert_eq!(sketch.estimated_cardinality(), 0);
+    assert_eq!(sketch.get_table_len(), snapshot.get_table_len());
+    assert_eq!(sketch.sample_size(), snapshot.sample_size());
+    assert_eq!(sketch, FrequencyCountSketch::new(256));
+}
+
+#[test]
+fn test_increment_unchecked() {
+    let mut checked = FrequencyCountSketch::new(512);
+    let mut unchecked = FrequencyCountSketch::new(512);
+    for i in 0..1_500u32 {
+        checked.increment(i % 500);
+        unchecked.increment_unchecked(i % 500);
+    }
+    assert!((0..500u32).all(|i| checked.frequency(i) < 15));
+    assert_eq!(unchecked, checked);
+
+    // A saturated counter wraps within its nibble instead of carrying into its neighbour
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.increment_by("key", 15);
+    let neighbours: Vec<u64> = sketch.raw_table().to_vec();
+    sketch.increment_unchecked("key");
+    assert_eq!(sketch.frequency("key"), 0);
+    let (words, nibbles) = sketch.locate("key");
+    for (&word, &nibble) in words.iter().zip(nibbles.iter()) {
+        let mask = 0xfu64 << (nibble << 2);
+        assert_eq!(sketch.raw_table()[word] & !mask, neighbours[word] & !mask);
+    }
+}
+
+#[test]
+fn test_merge_weighted() {
+    let mut long_term = FrequencyCountSketch::new(256);
+    let mut recent = FrequencyCountSketch::new(256);
+    long_term.increment_by("old", 8);
+    long_term.increment_by("shared", 3);
+    recent.increment_by("new", 8);
+    recent.increment_by("shared", 3);
+
+    let mut merged = long_term.clone();
+    merged.merge_weighted(&recent, 1, 1).unwrap();
+    let summed: Vec<u8> = long_term
+        .decode_counters()
+        .iter()
+        .zip(recent.decode_counters())
+        .map(|(&a, b)| (a + b).min(15))
+        .collect();
+    assert_eq!(merged.decode_counters(), summed);
+    assert_eq!(merged.frequency("shared"), 6);
+
+    let mut favor_recent = long_term.clone();
+    favor_recent.merge_weighted(&recent, 1, 4).unwrap();
+    assert_eq!(favor_recent.frequency("new"), 8);
+    assert_eq!(favor_recent.frequency("old"), 2);
+    let mut favor_old = long_term.clone();
+    favor_old.merge_weighted(&recent, 4, 1).unwrap();
+    assert_eq!(favor_old.frequency("old"), 8);
+    assert_eq!(favor_old.frequency("new"), 2);
+    assert_eq!(favor_old.check_invariants(), Ok(()));
+
+    assert_eq!(
+        merged.merge_weighted(&FrequencyCountSketch::new(1024), 1, 1),
+        Err(SketchError::TableLengthMismatch { expected: 256, actual: 1024 })
+    );
+    assert_eq!(
+        merged.merge_weighted(&FrequencyCountSketch::new_independent(256), 1, 1),
+        Err(SketchError::LayoutMismatch)
+    );
+}
+
+#[test]
+fn test_hybrid_frequency_sketch() {
+    let mut tiny = HybridFrequencySketch::new(8);
+    assert!(tiny.is_exact());
+    for key in 0..8u32 {
+        for _ in 0..key {
+            tiny.increment(key);
+        }
+    }
+    for key in 0..8u32 {
+        assert_eq!(tiny.frequency(key), key as u8);
+    }
+    assert_eq!(tiny.frequency(100u32), 0);
+
+    // 28 additions so far; the sample size of 80 halves the counts
+    for _ in 0..20 {
+        tiny.increment("hot");
+    }
+    assert_eq!(tiny.frequency("hot"), 15);
+    for i in 0..47u32 {
+        tiny.increment(1_000 + i);
+    }
+    assert_eq!(tiny.frequency(7u32), 3);
+    assert_eq!(tiny.frequency("hot"), 7);
+    assert_eq!(tiny.frequency(1_000u32), 0);
+
+    let large = HybridFrequencySketch::new(9);
+    assert!(!large.is_exact());
+}
+
+#[test]
+fn test_similarity() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    let mut other = FrequencyCountSketch::new(512);
+    for i in 0..200u32 {
+        sketch.increment_by(i, i % 7 + 1);
+        other.increment_by(i + 100, i % 3 + 1);
+    }
+    let cloned = sketch.clone();
+    assert!((sketch.similarity(&cloned).unwrap() - 1.0).abs() < 1e-9);
+    let different = sketch.similarity(&other).unwrap();
+    assert!(different < 0.9 && different > 0.0, "{}", different);
+    assert_eq!(sketch.similarity(&other).unwrap(), other.similarity(&sketch).unwrap());
+
+    let empty = FrequencyCountSketch::new(512);
+    assert_eq!(empty.similarity(&empty.clone()), Ok(1.0));
+    assert_eq!(empty.similarity(&sketch), Ok(0.0));
+    assert_eq!(
+        sketch.similarity(&FrequencyCountSketch::new(2048)),
+        Err(SketchError::TableLengthMismatch { expected: 512, actual: 2048 })
+    );
+}
+
+#[test]
+fn test_increment_touches_located_counters() {
+    for mut sketch in [FrequencyCountSketch::new(512), FrequencyCountSketch::new_independent(512)] {
+        for key in 0..64u32 {
+            let before = sketch.decode_counters();
+            let positions = sketch.counter_positions(key);
+            sketch.increment(key);
+            let after = sketch.decode_counters();
+            for (index, (&old, &new)) in before.iter().zip(after.iter()).enumerate() {
+                let expected = old + positions.iter().filter(|&&p| p == index).count() as u8;
+                assert_eq!(new, expected, "key {} counter {}", key, index);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_new_with_seed() {
+    let first = FrequencyCountSketch::new_with_seed(4096, 1);
+    let second = FrequencyCountSketch::new_with_seed(4096, 2);
+    let block = |sketch: &FrequencyCountSketch, key: u32| sketch.locate(key).0[0] / sketch.block_words();
+    let differing = (0..64u32).filter(|&key| block(&first, key) != block(&second, key)).count();
+    assert!(differing > 56, "{}", differing);
+    assert_ne!(block(&first, 7), block(&second, 7));
+    assert_eq!(first.hash_code(7), FrequencyCountSketch::new_with_seed(64, 1).hash_code(7));
+    assert_eq!(FrequencyCountSketch::new(64).hash_code(7), frequency_count_sketch::default_hash_code(7));
+
+    let mut seeded = FrequencyCountSketch::new_with_seed(64, 42);
+    for _ in 0..5 {
+        seeded.increment("key");
+        seeded.increment_bytes(b"raw");
+    }
+    assert_eq!(seeded.frequency("key"), 5);
+    assert_eq!(seeded.frequency_hashed(seeded.hash_code("key")), 5);
+    assert_eq!(seeded.frequency_bytes(b"raw"), 5);
+}
+
+#[test]
+fn test_generational_frequency_sketch() {
+    let mut sketch = GenerationalFrequencySketch::new(512);
+    for _ in 0..12 {
+        sketch.increment("old");
+    }
+    assert_eq!(sketch.frequency("old"), 12);
+    assert_eq!(sketch.current_generation(), 0);
+
+    sketch.set_generation(1);
+    assert_eq!(sketch.frequency("old"), 6);
+    for _ in 0..3 {
+        sketch.increment("new");
+    }
+    sketch.increment("old");
+    assert_eq!(sketch.frequency("old"), 7);
+
+    sketch.set_generation(3);
+    assert_eq!(sketch.frequency("old"), 1);
+    assert_eq!(sketch.frequency("new"), 0);
+
+    // The tags wrap around without resurrecting stale counts
+    for _ in 0..40 {
+        sketch.reset();
+    }
+    assert_eq!(sketch.current_generation(), 11);
+    assert_eq!(sketch.frequency("old"), 0);
+    sketch.set_generation(3);
+    assert_eq!(sketch.current_generation(), 3);
+    assert_eq!(sketch.frequency("old"), 0);
+}
+
+#[test]
+fn test_collision_rate() {
+    let keys: Vec<u32> = (0..2_000).collect();
+    let mut undersized = FrequencyCountSketch::new(64);
+    let mut well_sized = FrequencyCountSketch::new(1 << 16);
+    for &key in &keys {
+        undersized.increment(key);
+        well_sized.increment(key);
+    }
+    let crowded = undersized.collision_rate(&keys);
+    let roomy = well_sized.collision_rate(&keys);
+    assert!(crowded > roomy, "crowded {} roomy {}", crowded, roomy);
+    assert!(roomy < 0.05, "roomy {}", roomy);
+    assert_eq!(well_sized.collision_rate(&Vec::<u32>::new()), 0.0);
+}
+
+#[test]
+fn test_percentile() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    assert_eq!(sketch.percentile("hot"), 0.0);
+    for i in 0..300u32 {
+        sketch.increment_by(i, i % 4 + 1);
+    }
+    sketch.increment_by("hot", 20);
+    let histogram = sketch.histogram();
+    assert_eq!(histogram.iter().sum::<usize>(), sketch.counter_count());
+    assert!(histogram[15] >= 4);
+
+    assert!(sketch.percentile("hot") > 0.99);
+    assert!(sketch.percentile("cold") < sketch.percentile("hot"));
+}
+
+#[test]
+fn test_merge_max() {
+    let mut sketch = FrequencyCountSketch::new(256);
+    sketch.increment_by("a", 5);
+    sketch.increment_by("b", 2);
+    let original = sketch.clone();
+
+    sketch.merge_max(&original).unwrap();
+    assert_eq!(sketch, original);
+    let mut summed = original.clone();
+    summed.merge_weighted(&original, 1, 1).unwrap();
+    assert_eq!(summed.frequency("a"), 10);
+
+    let mut other = FrequencyCountSketch::new(256);
+    other.increment_by("a", 3);
+    other.increment_by("c", 7);
+    sketch.merge_max(&other).unwrap();
+    assert_eq!(sketch.frequency("a"), 5);
+    assert_eq!(sketch.frequency("b"), 2);
+    assert_eq!(sketch.frequency("c"), 7);
+    assert_eq!(
+        sketch.merge_max(&FrequencyCountSketch::new(1024)),
+        Err(SketchError::TableLengthMismatch { expected: 256, actual: 1024 })
+    );
+}
+
+#[test]
+fn test_sketch_merger() {
+    let mut replica = FrequencyCountSketch::new(256);
+    let mut target = FrequencyCountSketch::new(256);
+    for i in 0..300u32 {
+        replica.increment(i % 50);
+        target.increment(i % 70);
+    }
+    let bytes = replica.to_bytes();
+    let mut expected = target.clone();
+    expected.merge_weighted(&FrequencyCountSketch::from_bytes(&bytes, 256).unwrap(), 1, 1).unwrap();
+
+    let mut merger = SketchMerger::new(&mut target);
+    for chunk in bytes.chunks(13) {
+        merger.feed(chunk).unwrap();
+    }
+    merger.finish().unwrap();
+    assert_eq!(target, expected);
+
+    let mut short = FrequencyCountSketch::new(256);
+    let mut merger = SketchMerger::new(&mut short);
+    merger.feed(&bytes[..20]).unwrap();
+    assert_eq!(merger.feed(&bytes), Err(SketchError::InvalidByteLength { expected: 2048, actual: 2068 }));
+    assert_eq!(merger.finish(), Err(SketchError::InvalidByteLength { expected: 2048, actual: 20 }));
+}
+
+#[test]
+fn test_concurrent_frequency_sketch() {
+    let sketch = ConcurrentFrequencySketch::new(1000);
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..3 {
+                    for key in 0..100u32 {
+                        sketch.increment(key);
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(sketch.estimated_cardinality(), 1200);
+    for key in 0..100u32 {
+        assert!(sketch.frequency(key) >= 12, "key {}", key);
+    }
+
+    let mut sequential = FrequencyCountSketch::new(1000);
+    for key in 0..100u32 {
+        for _ in 0..12 {
+            sequential.increment(key);
+        }
+    }
+    for key in 0..100u32 {
+        assert_eq!(sketch.frequency(key), sequential.frequency(key));
+    }
+    sketch.reset();
+    assert_eq!(sketch.frequency(0u32), sequential.frequency(0u32) >> 1);
+}
+
+#[test]
+fn test_sliding_window_sketch() {
+    let mut sketch = SlidingWindowSketch::new(256, 3).unwrap();
+    assert_eq!(sketch.window_count(), 3);
+    for _ in 0..10 {
+        sketch.increment("expired");
+    }
+    sketch.advance();
+    for _ in 0..15 {
+        sketch.increment("steady");
+    }
+    sketch.advance();
+    for _ in 0..6 {
+        sketch.increment("steady");
+    }
+    assert_eq!(sketch.frequency("expired"), 10);
+    assert_eq!(sketch.frequency("steady"), 21);
+    assert_eq!(sketch.newest().frequency("steady"), 6);
+
+    sketch.advance();
+    assert_eq!(sketch.frequency("expired"), 0);
+    assert_eq!(sketch.frequency("steady"), 21);
+    sketch.advance();
+    sketch.advance();
+    assert_eq!(sketch.frequency("steady"), 0);
+
+    assert_eq!(SlidingWindowSketch::new(256, 0).unwrap_err(), SketchError::InvalidWindowCount(0));
+}
+
+#[test]
+fn test_frequency_corrected() {
+    let mut sketch = DepthFrequencySketch::<8>::new(8);
+    for key in 0..60u32 {
+        sketch.increment(key);
+    }
+    for _ in 0..3 {
+        sketch.increment("target");
+    }
+    let corrected = sketch.frequency_corrected("target");
+    assert!(corrected < sketch.frequency("target") as f64, "{}", corrected);
+    assert!(corrected <= 5.0, "{}", corrected);
+
+    // The default depth shares the layout, and so the estimate, of frequency_cmm
+    let mut shallow = DepthFrequencySketch::<4>::new(64);
+    let mut reference = FrequencyCountSketch::new(64);
+    for key in 0..300u32 {
+        shallow.increment(key % 37);
+        reference.increment(key % 37);
+    }
+    for key in 0..37u32 {
+        assert_eq!(shallow.frequency_corrected(key), reference.frequency_cmm(key), "key {}", key);
+    }
+    assert_eq!(DepthFrequencySketch::<3>::new(64).frequency_corrected("key"), 0.0);
+}
+
+#[test]
+fn test_mixer_golden_values() {
+    // Captured from the current mixers; any change here relocates every persisted counter
+    if cfg!(target_pointer_width = "64") {
+        let golden: [(u64, u64, u64); 5] = [
+            (0, 0, 0),
+            (1, 0x9fb3ab13e805b0e1, 0x0e82cd5e6b9e0e26),
+            (42, 0x379e9e11d8c13080, 0x93f4cd6222682403),
+            (0xdead_beef, 0xc65d57965d288b11, 0x683c7098f577d237),
+            (u64::MAX, 0x2a43d199faa00000, 0xeeef1e8a955d2380),
+        ];
+        for (hash_code, spread, rehash) in golden {
+            let block_hash = frequency_count_sketch::spread(hash_code);
+            assert_eq!(block_hash as u64, spread, "spread({:#x})", hash_code);
+            assert_eq!(frequency_count_sketch::rehash(block_hash) as u64, rehash, "rehash({:#x})", spread);
+        }
+    }
+}
+
+#[test]
+fn test_merge() {
+    let mut sketch = FrequencyCountSketch::new(256);
+    let mut other = FrequencyCountSketch::new(256);
+    sketch.increment_by("a", 4);
+    other.increment_by("a", 3);
+    other.increment_by("b", 2);
+    let mut expected = sketch.clone();
+    expected.merge_weighted(&other, 1, 1).unwrap();
+
+    let mut merged = sketch.clone();
+    merged.merge(&other).unwrap();
+    assert_eq!(merged, expected);
+    assert_eq!(merged.frequency("a"), 7);
+    sketch.expect_merge(&other);
+    assert_eq!(sketch, expected);
+
+    assert_eq!(
+        sketch.merge(&FrequencyCountSketch::new(1024)),
+        Err(SketchError::TableLengthMismatch { expected: 256, actual: 1024 })
+    );
+}
+
+#[test]
+#[should_panic(expected = "cannot merge a sketch of 1024 words into one of 256 words")]
+fn test_expect_merge_mismatch() {
+    FrequencyCountSketch::new(256).expect_merge(&FrequencyCountSketch::new(1024));
+}
+
+#[test]
+fn test_touch() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    assert_eq!(sketch.touch("key"), 0);
+    assert_eq!(sketch.frequency("key"), 1);
+    assert_eq!(sketch.touch("key"), 1);
+    assert_eq!(sketch.frequency("key"), 2);
+    assert_eq!(sketch.estimated_cardinality(), 2);
+
+    let mut touched = FrequencyCountSketch::new(64);
+    let mut reference = FrequencyCountSketch::new(64);
+    for i in 0..2_000u32 {
+        let before = reference.frequency(i % 137);
+        reference.increment(i % 137);
+        assert_eq!(touched.touch(i % 137), before);
+    }
+    assert_eq!(touched, reference);
+}
+
+#[test]
+fn test_u64_keys() {
+    let mut sketch = FrequencyCountSketch::new(256);
+    let mut hashed = FrequencyCountSketch::new(256);
+    for i in 0..1_000u64 {
+        let fingerprint = (i % 90).wrapping_mul(0x9e3779b97f4a7c15);
+        sketch.increment_u64(fingerprint);
+        hashed.increment_hashed(fingerprint);
+    }
+    assert_eq!(sketch, hashed);
+    for i in 0..90u64 {
+        let fingerprint = i.wrapping_mul(0x9e3779b97f4a7c15);
+        assert_eq!(sketch.frequency_u64(fingerprint), hashed.frequency_hashed(fingerprint));
+    }
+    assert!(sketch.frequency_u64(0x9e3779b97f4a7c15) > 0);
+}
+
+#[test]
+fn test_reset_and_report() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    for key in 0..100u32 {
+        sketch.increment_by(key, 6);
+    }
+    let total: u64 = sketch.decode_counters().iter().map(|&c| c as u64).sum();
+    assert!(total >= 2_400);
+    let removed = sketch.reset_and_report();
+    let remaining: u64 = sketch.decode_counters().iter().map(|&c| c as u64).sum();
+    assert_eq!(removed + remaining, total);
+    assert!(removed.abs_diff(total / 2) <= total / 10, "removed {} of {}", removed, total);
+    assert_eq!(FrequencyCountSketch::new(64).reset_and_report(), 0);
+}
+
+#[test]
+fn test_increment_checked_key() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    let mut reported = 0;
+    for _ in 0..1_000 {
+        if sketch.increment_checked_key(()) {
+            reported += 1;
+        }
+    }
+    // Every zero-sized key lands on the same four counters of a single block
+    assert_eq!(reported, 1_000 - frequency_count_sketch::SUSPICIOUS_HASH_RUN + 1);
+    assert_eq!(sketch.frequency(()), 15);
+    assert_eq!(sketch.decode_counters().iter().filter(|&&c| c != 0).count(), 4);
+
+    for i in 0..1_000u32 {
+        assert!(!sketch.increment_checked_key(i));
+    }
+}
+
+#[test]
+fn test_reset_size_does_not_underflow() {
+    // Every counter is odd but no increment is accounted for, so halving finds more odd counters
+    // than the accounting can absorb
+    let mut sketch = FrequencyCountSketch::from_raw_parts(&[0x1111_1111_1111_1111; 8], 8, 0).unwrap();
+    sketch.reset();
+    assert_eq!(sketch.estimated_cardinality(), 0);
+    assert_eq!(sketch.check_invariants(), Ok(()));
+    sketch.increment(1);
+    assert_eq!(sketch.estimated_cardinality(), 1);
+}
+
+#[test]
+fn test_percentile_among() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    let population: Vec<u32> = (0..20).collect();
+    for &key in &population {
+        sketch.increment_by(key, key % 5);
+    }
+    sketch.increment_by(19u32, 15);
+    assert_eq!(sketch.percentile_among(19, &population), 1.0);
+    assert_eq!(sketch.percentile_among(0, &population), 0.2);
+    assert!(sketch.percentile_among(2, &population) < sketch.percentile_among(4, &population));
+    assert_eq!(sketch.percentile_among(1, &[]), 0.0);
+}
+
+#[test]
+fn test_estimate_absolute() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    assert_eq!(sketch.estimate_absolute("steady"), 0.0);
+    let mut previous = 0.0;
+    for round in 1..=4u64 {
+        for i in 0..640u32 {
+            sketch.increment(i % 200);
+            if i % 40 == 0 {
+                sketch.increment("steady");
+            }
+        }
+        sketch.reset();
+        assert!(sketch.reset_count() >= round);
+        let absolute = sketch.estimate_absolute("steady");
+        assert!(absolute > previous, "round {}: {} after {}", round, absolute, previous);
+        previous = absolute;
+    }
+    assert_eq!(
+        sketch.estimate_absolute("steady"),
+        sketch.frequency("steady") as f64 * 2f64.powi(sketch.reset_count() as i32)
+    );
+
+    // Explicit agings count as resets too, and are undone like them
+    let mut aged = FrequencyCountSketch::new(64);
+    aged.increment_by("key", 8);
+    aged.decay(3, 4);
+    assert_eq!(aged.reset_count(), 1);
+    assert_eq!(aged.estimate_absolute("key"), 12.0);
+    aged.reset_weighted(4);
+    assert_eq!(aged.reset_count(), 2);
+    aged.decay_to_mass(4);
+    assert_eq!(aged.reset_count(), 3);
+    aged.decay_to_mass(u64::MAX);
+    assert_eq!(aged.reset_count(), 3);
+}
+
+#[cfg(feature = "word32")]
+#[test]
+fn test_word32_frequency_sketch() {
+    let mut narrow = Word32FrequencySketch::new(512);
+    let mut wide = FrequencyCountSketch::new(512);
+    assert_eq!(narrow.get_table_len(), wide.get_table_len() * 2);
+    assert_eq!(narrow.memory_usage_bytes(), wide.memory_usage_bytes());
+    for key in workload::zipfian_stream(2_000, 20_000, 1.0) {
+        narrow.increment(key);
+        wide.increment(key);
+    }
+    for key in 0..2_000u64 {
+        assert_eq!(narrow.frequency(key), wide.frequency(key), "key {}", key);
+    }
+    narrow.reset();
+    wide.reset();
+    for key in 0..2_000u64 {
+        assert_eq!(narrow.frequency(key), wide.frequency(key), "key {}", key);
+    }
+}
+
+#[test]
+fn test_seen_this_window() {
+    let mut sketch = FrequencyCountSketch::new(512);
+    assert!(!sketch.seen_this_window("once"));
+    sketch.increment("once");
+    sketch.increment_by("often", 3);
+    assert!(sketch.seen_this_window("once"));
+
+    sketch.reset();
+    assert!(!sketch.seen_this_window("once"));
+    assert!(sketch.seen_this_window("often"));
+    sketch.reset();
+    assert!(!sketch.seen_this_window("often"));
+}
+
+#[test]
+fn test_byteswap_table() {
+    let mut sketch = FrequencyCountSketch::new(256);
+    for i in 0..500u32 {
+        sketch.increment(i % 40);
+    }
+    let original = sketch.clone();
+
+    sketch.byteswap_table();
+    assert_ne!(sketch.raw_table(), original.raw_table());
+    // Simulates bytes written by a host of the other endianness
+    let foreign: Vec<u8> = original.raw_table().iter().flat_map(|w| w.swap_bytes().to_ne_bytes()).collect();
+    assert_eq!(sketch.as_byte_slice(), foreign.as_slice());
+    let mut loaded = FrequencyCountSketch::from_byte_slice(&foreign, 256).unwrap();
+    loaded.byteswap_table();
+    assert_eq!(loaded.raw_table(), original.raw_table());
+
+    sketch.byteswap_table();
+    assert_eq!(sketch, original);
+    for i in 0..40u32 {
+        assert_eq!(sketch.frequency(i), original.frequency(i));
+    }
+}
+
+#[test]
+fn test_max_frequency() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    assert_eq!(sketch.max_frequency(), 15);
+    assert_eq!(sketch.max_frequency(), frequency_count_sketch::MAX_FREQUENCY);
+    sketch.increment_by("key", 40);
+    assert_eq!(sketch.frequency("key"), sketch.max_frequency());
+}
+
+#[test]
+fn test_depth_and_max_frequency_constants() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    assert_eq!(sketch.counters_for("key").len(), frequency_count_sketch::DEPTH);
+    sketch.increment_by("key", u32::from(frequency_count_sketch::MAX_FREQUENCY) + 1);
+    assert_eq!(sketch.frequency("key"), frequency_count_sketch::MAX_FREQUENCY);
+    assert_eq!(sketch.counters_for("key"), [frequency_count_sketch::MAX_FREQUENCY; frequency_count_sketch::DEPTH]);
+}
+
+#[test]
+fn test_shrink_to() {
+    let mut sketch = FrequencyCountSketch::new(4096);
+    for i in 0..3_000u32 {
+        sketch.increment(1_000 + i);
+    }
+    let hot = ["a", "b", "c", "d"];
+    for (n, key) in hot.iter().enumerate() {
+        sketch.increment_by(key, 6 + 2 * n as u32);
+    }
+    let before: Vec<u8> = hot.iter().map(|key| sketch.frequency(key)).collect();
+
+    assert!(!sketch.shrink_to(8192, &hot));
+    assert!(sketch.shrink_to(256, &hot));
+    assert_eq!(sketch.get_table_len(), FrequencyCountSketch::new(256).get_table_len());
+    for (key, &count) in hot.iter().zip(&before) {
+        let after = sketch.frequency(key);
+        assert!(after >= count && after <= count + 2, "{}: {} became {}", key, count, after);
+    }
+    let tail = (0..3_000u32).filter(|i| sketch.frequency(1_000 + i) > 0).count();
+    assert!(tail < 100, "{}", tail);
+}
+
+#[test]
+fn test_increment_batch_with_aging() {
+    let items: Vec<u32> = workload::zipfian_stream(300, 5_003, 0.9).map(|key| key as u32).collect();
+    let mut batched = FrequencyCountSketch::new(64);
+    let mut looped = FrequencyCountSketch::new(64);
+    batched.increment_batch_with_aging(&items);
+    for item in &items {
+        looped.increment(item);
+    }
+    assert!(looped.reset_count() > 1);
+    assert_eq!(batched, looped);
+    assert_eq!(batched.reset_count(), looped.reset_count());
+    for key in 0..300u32 {
+        assert_eq!(batched.frequency(key), looped.frequency(key));
+    }
+    batched.increment_batch_with_aging(Vec::<u32>::new());
+    assert_eq!(batched, looped);
+}
+
+#[test]
+fn test_frequency_median_of_four() {
+    let sketch = FrequencyCountSketch::new(64);
+    let (words, nibbles) = sketch.locate("key");
+    // Three counters still hold 12 while the fourth was already halved by a partial sweep
+    let mut table = sketch.raw_table().to_vec();
+    for (i, value) in [12u64, 12, 6, 12].into_iter().enumerate() {
+        table[words[i]] |= value << (nibbles[i] << 2);
+    }
+    let decayed = FrequencyCountSketch::from_raw_parts(&table, 64, 12).unwrap();
+    assert_eq!(decayed.frequency("key"), 6);
+    assert_eq!(decayed.frequency_median("key"), 12);
+
+    let mut fresh = FrequencyCountSketch::new(64);
+    fresh.increment_by("key", 5);
+    assert_eq!(fresh.frequency_median("key"), 5);
+}
+
+#[test]
+fn test_is_warm() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    assert!(!sketch.is_warm());
+    let sample_size = sketch.sample_size();
+    for i in 0..sample_size - 1 {
+        sketch.increment(i);
+    }
+    assert!(!sketch.is_warm());
+    // The increment reaching the sample size resets the sketch, which stays warm
+    sketch.increment(sample_size);
+    assert!(sketch.is_warm());
+
+    let mut sketch = FrequencyCountSketch::new_with_warmup(64, 10);
+    for i in 0..9 {
+        sketch.increment(i);
+    }
+    assert!(!sketch.is_warm());
+    sketch.increment(9);
+    assert!(sketch.is_warm());
+}
+
+#[test]
+fn test_accounting_snapshot_round_trip() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    for i in 0..2000 {
+        sketch.increment(i % 300);
+    }
+    let accounting = sketch.accounting_snapshot();
+    assert_eq!(accounting, (sketch.estimated_cardinality(), sketch.sample_size(), sketch.reset_count()));
+    assert!(accounting.2 > 0);
+
+    let mut restored = FrequencyCountSketch::new(64);
+    let (size, sample_size, reset_count) = accounting;
+    restored.restore_accounting(size, sample_size, reset_count).unwrap();
+    assert_eq!(restored.accounting_snapshot(), accounting);
+    assert!(restored.raw_table().iter().all(|&w| w == 0));
+
+    assert!(restored.restore_accounting(sample_size + 1, sample_size, 0).is_err());
+    assert_eq!(restored.accounting_snapshot(), accounting);
+
+    // Extreme checkpoints are accepted and every later update saturates
+    let mut extreme = FrequencyCountSketch::new(64);
+    extreme.restore_accounting(usize::MAX - 1, usize::MAX, u64::MAX).unwrap();
+    let other = extreme.clone();
+    extreme.merge_weighted(&other, 3, 2).unwrap();
+    assert_eq!(extreme.estimated_cardinality(), usize::MAX - 1);
+    extreme.reset();
+    extreme.increment("key");
+    assert_eq!(extreme.reset_count(), u64::MAX);
+    assert!(!extreme.estimate_absolute("key").is_nan());
+}
+
+#[test]
+fn test_diff() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    for i in 0..100 {
+        sketch.increment(i);
+    }
+    let mut replica = sketch.clone();
+    assert!(sketch.diff(&replica).unwrap().is_empty());
+
+    let before = replica.frequency("extra");
+    replica.increment("extra");
+    let mut positions = replica.counter_positions("extra");
+    positions.sort_unstable();
+    let diff = sketch.diff(&replica).unwrap();
+    let indices: Vec<usize> = diff.iter().map(|&(i, _, _)| i).collect();
+    assert_eq!(indices, positions);
+    assert!(diff.iter().all(|&(_, ours, theirs)| theirs == ours + 1 && ours >= before));
+
+    assert!(sketch.diff(&FrequencyCountSketch::new(1024)).is_err());
+}
+
+#[test]
+fn test_is_undersized() {
+    let keys: Vec<u64> = (0..1000).collect();
+    let mut undersized = FrequencyCountSketch::new(64);
+    let mut right_sized = FrequencyCountSketch::new(1000);
+    for &key in &keys {
+        undersized.increment(key);
+        right_sized.increment(key);
+    }
+    assert!(undersized.is_undersized(&keys));
+    assert!(!right_sized.is_undersized(&keys));
+}
+
+#[test]
+fn test_frequency_cmm_subtracts_noise() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.set_aging_enabled(false);
+    for i in 0..400 {
+        sketch.increment(i);
+    }
+    for _ in 0..4 {
+        sketch.increment("hot");
+    }
+    let raw = sketch.frequency("hot") as f64;
+    let cmm = sketch.frequency_cmm("hot");
+    assert!(cmm < raw, "cmm {} should be below the raw estimate {}", cmm, raw);
+    assert!(cmm > 0.0);
+
+    assert_eq!(FrequencyCountSketch::new(64).frequency_cmm("hot"), 0.0);
+}
+
+#[test]
+fn test_hottest_blocks() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    for i in 0..200 {
+        sketch.increment(i);
+    }
+    sketch.increment_by("hot", 15);
+    let hot_block = sketch.locate("hot").0[0] / sketch.block_words();
+
+    let hottest = sketch.hottest_blocks(3);
+    assert_eq!(hottest.len(), 3);
+    assert_eq!(hottest[0].0, hot_block);
+    assert!(hottest[0].1 >= 60);
+    assert!(hottest.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    assert_eq!(sketch.hottest_blocks(usize::MAX).len(), sketch.raw_table().len() / sketch.block_words());
+}
+
+#[test]
+fn test_absorb() {
+    let mut small = FrequencyCountSketch::new(64);
+    small.increment_by("hot", 9);
+    small.increment_by("warm", 4);
+    for i in 0..50 {
+        small.increment(i);
+    }
+    let mut large = FrequencyCountSketch::new(4096);
+    large.increment_by("hot", 2);
+    large.absorb(&small, &["hot", "warm"]);
+    assert_eq!(large.frequency("hot"), 2 + small.frequency("hot"));
+    assert_eq!(large.frequency("warm"), small.frequency("warm"));
+    assert!(large.frequency("hot") >= 11);
+}
+
+#[test]
+fn test_sketch_pool_reuses_cleared_sketches() {
+    let pool = SketchPool::new(1024);
+    let buffer = {
+        let mut sketch = pool.acquire();
+        for i in 0..5000 {
+            sketch.increment(i % 100);
+        }
+        assert!(sketch.frequency(7) > 0);
+        sketch.raw_table().as_ptr()
+    };
+    assert_eq!(pool.available(), 1);
+
+    let sketch = pool.acquire();
+    assert_eq!(pool.available(), 0);
+    assert_eq!(sketch.raw_table().as_ptr(), buffer);
+    assert_eq!(*sketch, FrequencyCountSketch::new(1024));
+    assert_eq!(sketch.reset_count(), 0);
+}
+
+#[test]
+fn test_sketch_pool_returns_pristine_sketches() {
+    let pool = SketchPool::new(1024);
+    let resets = Arc::new(Mutex::new(0u32));
+    {
+        let mut sketch = pool.acquire();
+        let counter = resets.clone();
+        sketch.on_reset(move |_| *counter.lock().unwrap() += 1);
+    }
+    // The next owner does not inherit the previous owner's hook
+    pool.acquire().reset();
+    assert_eq!(*resets.lock().unwrap(), 0);
+    assert_eq!(pool.available(), 1);
+
+    // A resized sketch is dropped rather than handed out with the wrong dimensions
+    {
+        let mut sketch = pool.acquire();
+        assert!(sketch.compact(8));
+    }
+    assert_eq!(pool.available(), 0);
+    {
+        let mut sketch = pool.acquire();
+        assert!(sketch.shrink_to(16, &[1u32]));
+    }
+    assert_eq!(pool.available(), 0);
+    assert_eq!(*pool.acquire(), FrequencyCountSketch::new(1024));
+}
+
+#[test]
+fn test_clear() {
+    let mut sketch = FrequencyCountSketch::new(64);
+    for i in 0..2000 {
+        sketch.increment(i % 70);
+    }
+    sketch.clear();
+    assert_eq!(sketch, FrequencyCountSketch::new(64));
+    assert_eq!(sketch.accounting_snapshot(), FrequencyCountSketch::new(64).accounting_snapshot());
+    assert!(!sketch.is_warm());
+
+    // A crowded adaptive sketch also forgets the sample size it tuned
+    let mut adaptive = FrequencyCountSketch::new_adaptive(64);
+    for i in 0..5000 {
+        adaptive.increment(i);
+    }
+    assert!(adaptive.sample_size() < FrequencyCountSketch::new(64).sample_size());
+    adaptive.clear();
+    assert_eq!(adaptive.accounting_snapshot(), FrequencyCountSketch::new_adaptive(64).accounting_snapshot());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_clear_discards_wall_clock_progress() {
+    let start = Instant::now();
+    let period = Duration::from_secs(60);
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.set_reset_period(Some(period));
+    sketch.increment_at_time("key", start);
+    assert!(!sketch.maybe_reset_after(Duration::from_secs(50), period));
+    sketch.clear();
+
+    // Neither the accumulated 50 seconds nor the instant before the clear age the cleared sketch
+    sketch.increment_by("key", 4);
+    assert!(!sketch.maybe_reset_after(Duration::from_secs(50), period));
+    sketch.increment_at_time("key", start + Duration::from_secs(120));
+    assert_eq!(sketch.frequency("key"), 5);
+    assert_eq!(sketch.reset_count(), 0);
+}
+
+#[test]
+fn test_shrink_to_interrupted_leaves_sketch_valid() {
+    let mut sketch = FrequencyCountSketch::new(4096);
+    let keys: Vec<u32> = (0..40).collect();
+    for &key in &keys {
+        sketch.increment_by(key, 15);
+    }
+    let panicked = Arc::new(AtomicBool::new(false));
+    let flag = panicked.clone();
+    sketch.on_reset(move |_| {
+        if !flag.swap(true, AtomicOrdering::SeqCst) {
+            panic!("reset hook failure");
+        }
+    });
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sketch.shrink_to(8, &keys)));
+    assert!(result.is_err());
+    assert!(panicked.load(AtomicOrdering::SeqCst));
+
+    // The failed shrink leaves a consistent, smaller sketch rather than a torn one
+    assert_eq!(sketch.check_invariants(), Ok(()));
+    assert_eq!(sketch.raw_table().len(), FrequencyCountSketch::new(8).raw_table().len());
+    sketch.increment("after");
+    assert!(sketch.frequency("after") >= 1);
+}
+
+#[test]
+fn test_recency_frequency_sketch() {
+    let mut sketch = RecencyFrequencySketch::new(1024, 2).unwrap();
+    for _ in 0..15 {
+        sketch.increment("old");
+    }
+    assert_eq!(sketch.frequency_recent("old"), 15);
+
+    // Idle for two windows, the key still has counts but no longer reads as recent
+    sketch.reset();
+    sketch.increment("new");
+    assert_eq!(sketch.frequency_recent("old"), 7);
+    sketch.reset();
+    assert_eq!(sketch.frequency("old"), 3);
+    assert_eq!(sketch.frequency_recent("old"), 0);
+    assert_eq!(sketch.frequency_recent("new"), 0);
+
+    // Seen again, the key is recent with its aged count
+    sketch.increment("old");
+    assert_eq!(sketch.frequency_recent("old"), 4);
+
+    // Tags survive the wrap-around of the window index without aliasing
+    for _ in 0..15 {
+        sketch.reset();
+    }
+    assert_eq!(sketch.frequency_recent("old"), 0);
+    assert!(RecencyFrequencySketch::new(64, 0).is_err());
+}
+
+#[test]
+fn test_nonempty_blocks() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    assert_eq!(sketch.nonempty_blocks().count(), 0);
+    sketch.increment("a");
+    sketch.increment("b");
+    let block_of = |key: &str| sketch.locate(key).0[0] / sketch.block_words();
+    let mut touched = vec![block_of("a"), block_of("b")];
+    touched.sort_unstable();
+    touched.dedup();
+    assert_eq!(sketch.nonempty_blocks().collect::<Vec<_>>(), touched);
+}
+
+#[test]
+fn test_for_distinct_keys() {
+    let loose = FrequencyCountSketch::for_distinct_keys(1000, 0.6).unwrap();
+    let tight = FrequencyCountSketch::for_distinct_keys(1000, 0.1).unwrap();
+    assert!(tight.raw_table().len() > loose.raw_table().len());
+    assert_eq!(tight.sample_size(), FrequencyCountSketch::new(1000).sample_size());
+
+    // The predicted rate holds for the keys actually placed
+    let keys: Vec<u32> = (0..1000).collect();
+    let measured = FrequencyCountSketch::collision_estimate(tight.raw_table().len(), keys.iter());
+    assert!(measured <= 0.15, "measured collision rate {}", measured);
+
+    assert_eq!(FrequencyCountSketch::for_distinct_keys(1, 0.0).unwrap().raw_table().len(), 8);
+    for target in [0.0, -1.0, f64::NAN] {
+        assert!(matches!(
+            FrequencyCountSketch::for_distinct_keys(1000, target),
+            Err(SketchError::UnreachableCollisionRate(_))
+        ));
+    }
+}
+
+#[test]
+fn test_decay_to_mass() {
+    let mut sketch = FrequencyCountSketch::new(1024);
+    sketch.increment_by("hot", 15);
+    sketch.increment_by("warm", 8);
+    sketch.increment_by("cool", 3);
+    assert_eq!(sketch.total_mass(), 4 * (15 + 8 + 3));
+
+    sketch.decay_to_mass(50);
+    assert!(sketch.total_mass() <= 50);
+    assert!(sketch.frequency("hot") >= sketch.frequency("warm"));
+    assert!(sketch.frequency("warm") >= sketch.frequency("cool"));
+    assert!(sketch.frequency("hot") > sketch.frequency("cool"));
+
+    // A mass already within the target is left alone
+    let before = sketch.clone();
+    sketch.decay_to_mass(1000);
+    assert_eq!(sketch, before);
+}
+
+#[test]
+fn test_frequency_interval() {
+    let mut sketch = FrequencyCountSketch::new(256);
+    sketch.set_aging_enabled(false);
+    sketch.increment_by("key", 5);
+    let (estimate, upper) = sketch.frequency_interval("key");
+    assert_eq!(estimate, sketch.frequency("key"));
+    assert!(estimate as u32 <= upper);
+    let narrow = upper - estimate as u32;
+
+    for i in 0..2000 {
+        sketch.increment(i);
+    }
+    let (estimate, upper) = sketch.frequency_interval("key");
+    assert!(estimate >= 5 && estimate as u32 <= upper);
+    assert!(upper - estimate as u32 > narrow);
+}
+
+#[test]
+fn test_from_raw() {
+    let mut original = FrequencyCountSketch::new(256);
+    for i in 0..300 {
+        original.increment(i % 40);
+    }
+    let (size, sample_size, _) = original.accounting_snapshot();
+    let restored = FrequencyCountSketch::from_raw(original.raw_table(), 256, sample_size, size).unwrap();
+    assert_eq!(restored, original);
+    assert_eq!(restored.block_mask(), original.block_mask());
+
+    let custom = FrequencyCountSketch::from_raw(&[0; 64], 100, 50, 10).unwrap();
+    assert_eq!(custom.accounting_snapshot(), (10, 50, 0));
+    assert_eq!(custom.block_mask(), 7);
+
+    for len in [0, 4, 12, 100] {
+        assert_eq!(
+            FrequencyCountSketch::from_raw(&vec![0; len], 64, 640, 0).unwrap_err(),
+            SketchError::InvalidTableLength(len)
+        );
+    }
+    assert!(FrequencyCountSketch::from_raw(&[0; 64], 64, 10, 11).is_err());
+    assert!(FrequencyCountSketch::from_raw(&[0; 64], 64, 0, 0).is_err());
+}
+
+#[test]
+fn test_touched_words() {
+    for mut sketch in [FrequencyCountSketch::new(1024), FrequencyCountSketch::new_independent(1024)] {
+        for i in 0..300 {
+            sketch.increment(i);
+        }
+        let before = sketch.raw_table().to_vec();
+        let mut touched = sketch.touched_words("key");
+        sketch.increment("key");
+        let mut modified: Vec<usize> = (0..before.len()).filter(|&i| before[i] != sketch.raw_table()[i]).collect();
+        touched.sort_unstable();
+        modified.sort_unstable();
+        assert_eq!(modified, touched);
+    }
+}
+
+#[test]
+fn test_arithmetic_boundaries() {
+    // Hashing the extreme values stays within the u128 intermediate range
+    assert_eq!(frequency_count_sketch::spread(0), 0);
+    assert_ne!(frequency_count_sketch::spread(u64::MAX), frequency_count_sketch::spread(u64::MAX - 1));
+    assert_eq!(frequency_count_sketch::ceiling_power_of_two(i32::MIN), 1 << 30);
+    assert_eq!(frequency_count_sketch::ceiling_power_of_two(i32::MAX), 1 << 30);
+    assert_eq!(frequency_count_sketch::ceiling_power_of_two(0), 1);
+
+    // The largest maximum size is clamped without allocating the table
+    let (table_len, maximum, sample_size) = frequency_count_sketch::dimensions_for(usize::MAX);
+    assert_eq!(table_len, frequency_count_sketch::MAX_TABLE_LEN);
+    assert_eq!(sample_size, maximum * 10);
+
+    let mut empty = FrequencyCountSketch::new(0);
+    empty.reset();
+    assert_eq!(empty.reset_and_report(), 0);
+    assert_eq!(empty.estimated_cardinality(), 0);
+
+    // A full sample of the widest accounting saturates instead of overflowing
+    let mut sketch = FrequencyCountSketch::new(64);
+    sketch.set_aging_enabled(false);
+    sketch.restore_accounting(usize::MAX, usize::MAX, u64::MAX).unwrap();
+    sketch.increment("key");
+    assert_eq!(sketch.estimated_cardinality(), usize::MAX);
+    sketch.decay(3, 4);
+    assert_eq!(sketch.estimated_cardinality(), usize::MAX / 4 * 3 + 2);
+    let mut decaying = FrequencyCountSketch::new_with_decay(64, 0.75).unwrap();
+    decaying.restore_accounting(usize::MAX, usize::MAX, 0).unwrap();
+    decaying.reset();
+    assert_eq!(decaying.estimated_cardinality(), usize::MAX / 4 * 3 + 2);
+
+    // The reset count saturates rather than wrapping
+    let mut worn = FrequencyCountSketch::new(64);
+    worn.restore_accounting(0, 640, u64::MAX).unwrap();
+    worn.reset();
+    assert_eq!(worn.reset_count(), u64::MAX);
+
+    // A chunked merge into the widest accounting saturates at the sample size as well
+    let mut source = FrequencyCountSketch::new(64);
+    source.increment_by("key", 15);
+    let mut target = FrequencyCountSketch::new(64);
+    target.restore_accounting(usize::MAX - 1, usize::MAX, 0).unwrap();
+    let mut merger = SketchMerger::new(&mut target);
+    merger.feed(&source.to_bytes()).unwrap();
+    merger.finish().unwrap();
+    assert_eq!(target.estimated_cardinality(), usize::MAX - 1);
+}
@@ -0,0 +1,94 @@
+use std::cmp::min;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use crate::sketch::frequency_count_sketch::{
+    counted_size, default_hash_code, derive_positions, dimensions_for, halve_table, spread, CounterWord,
+};
+
+/// A variant of <tt>FrequencyCountSketch</tt> whose table is made of 32-bit words holding 8 counters
+/// each, for targets such as 32-bit microcontrollers where 64-bit arithmetic is emulated. Every
+/// 64-bit word of the default layout is split into its low and high halves, so an element's
+/// counters sit in the same 64-byte block and the sketch estimates exactly the same frequencies as
+/// <tt>FrequencyCountSketch::new</tt> for the same maximum size and stream.
+#[derive(Debug, Clone)]
+pub struct Word32FrequencySketch {
+    // Frequency reduction threshold
+    sample_size: usize,
+    block_mask: usize,
+    // Access frequency container
+    table: Vec<u32>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Word32FrequencySketch {
+    /// Initializes a sketch that can estimate the popularity of elements given the maximum size of
+    /// the cache, with as many counters as <tt>FrequencyCountSketch::new</tt>.
+    pub fn new(maximum_size: usize) -> Self {
+        let (table_len, maximum, sample_size) = dimensions_for(maximum_size);
+        Self {
+            sample_size,
+            block_mask: (table_len >> 3) - 1,
+            table: vec![0; table_len << 1],
+            size: 0,
+            max_size: maximum,
+        }
+    }
+
+    /// Return max size of this sketch
+    pub fn get_max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return the table length of this sketch in 32-bit words
+    pub fn get_table_len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Return the number of bytes used by the counter table.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.table.len() * size_of::<u32>()
+    }
+
+    /// Return the estimated number of occurrences of an element, up to the maximum (15).
+    pub fn frequency<E: Hash>(&self, e: E) -> u8 {
+        self.positions(default_hash_code(e))
+            .iter()
+            .map(|&(word, nibble)| ((self.table[word] >> (nibble << 2)) & 0xf) as u8)
+            .fold(15, min)
+    }
+
+    /// Increments the popularity of the element if it does not exceed the maximum (15). The popularity
+    /// of all elements will be periodically down sampled when the observed events exceed a threshold.
+    pub fn increment<E: Hash>(&mut self, e: E) {
+        let mut added = false;
+        for (word, nibble) in self.positions(default_hash_code(e)) {
+            added |= self.increment_at(word, nibble);
+        }
+
+        if added {
+            self.size = counted_size(self.size, self.sample_size);
+            if self.size >= self.sample_size {
+                self.reset();
+            }
+        }
+    }
+
+    /// Reduces every counter by half of its original value.
+    pub fn reset(&mut self) {
+        self.size = halve_table(&mut self.table, self.size);
+    }
+
+    /// Return the 32-bit word and nibble index of each of the element's counters, the halves of the
+    /// 64-bit words that <tt>FrequencyCountSketch</tt> uses.
+    fn positions(&self, hash_code: u64) -> [(usize, usize); 4] {
+        let (words, nibbles) = derive_positions(self.block_mask, 3, spread(hash_code));
+        std::array::from_fn(|i| ((words[i] << 1) + (nibbles[i] >> 3), nibbles[i] & 7))
+    }
+
+    /// Increments the specified counter by 1 if it is not already at the maximum value (15).
+    fn increment_at(&mut self, i: usize, j: usize) -> bool {
+        self.table[i].increment_nibble(j)
+    }
+}
@@ -0,0 +1,47 @@
+use std::iter;
+
+use crate::sketch::frequency_count_sketch::FrequencyCountSketch;
+
+/// The seed of the synthetic streams, fixed so that benchmarks and tests are reproducible.
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Return a stream of <tt>n</tt> keys drawn from a Zipf distribution over the ranks
+/// <tt>0..key_space</tt>, where rank <tt>k</tt> is drawn with probability proportional to
+/// <tt>1 / (k + 1)^skew</tt>. Key 0 is therefore the most popular, a skew of 0 is uniform and
+/// typical cache workloads have a skew between 0.6 and 1.2. The stream is deterministic.
+pub fn zipfian_stream(key_space: usize, n: usize, skew: f64) -> impl Iterator<Item = u64> {
+    let mut total = 0.0;
+    let cdf: Vec<f64> = (1..=key_space.max(1))
+        .map(|rank| {
+            total += (rank as f64).powf(-skew);
+            total
+        })
+        .collect();
+    let mut state = SEED;
+    iter::repeat_with(move || {
+        let target = next_unit(&mut state) * total;
+        cdf.partition_point(|&c| c <= target).min(cdf.len() - 1) as u64
+    })
+    .take(n)
+}
+
+/// Builds a sketch for the given maximum size from a Zipf-distributed stream of <tt>n</tt> keys over
+/// as many ranks as the maximum size, see <tt>zipfian_stream</tt>. Useful for benchmarks and for
+/// realistic tests of admission behavior.
+pub fn load_zipfian(maximum_size: usize, n: usize, skew: f64) -> FrequencyCountSketch {
+    let mut sketch = FrequencyCountSketch::new(maximum_size);
+    for key in zipfian_stream(maximum_size, n, skew) {
+        sketch.increment(key);
+    }
+    sketch
+}
+
+/// Advances the SplitMix64 generator and return a uniform value in [0, 1).
+fn next_unit(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}